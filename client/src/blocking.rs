@@ -0,0 +1,227 @@
+//! Blocking Keyring Program Client
+//!
+//! For callers without an async runtime, such as CLI tools. The client runs
+//! over a synchronous backend, so every call completes on the calling
+//! thread. A blocking `RpcClient` is one, so
+//! `BlockingKeyring::new(Arc::new(rpc_client), ..)` talks to a cluster
+//! directly.
+
+use {
+    crate::{
+        error::KeyringError,
+        keyring::{Keyring, KeystoreWrite},
+        keystore::{EncryptionKeyConfig, Keystore, KeystoreEntry},
+    },
+    async_trait::async_trait,
+    futures::executor::block_on,
+    solana_rpc_client::rpc_client::RpcClient,
+    solana_sdk::{
+        account::Account, hash::Hash, pubkey::Pubkey, signer::Signer, transaction::Transaction,
+    },
+    spl_token_client::client::{
+        ProgramClient, ProgramClientResult, ProgramRpcClientSendTransaction, RpcClientResponse,
+        SendTransaction,
+    },
+    std::{collections::HashMap, fmt, sync::Arc},
+};
+
+/// Synchronous counterpart of `ProgramClient`, implemented for the
+/// blocking `RpcClient`
+pub trait BlockingProgramClient<ST>: Send + Sync
+where
+    ST: SendTransaction,
+{
+    /// Fetch the minimum balance for a rent-exempt account of the given size
+    fn get_minimum_balance_for_rent_exemption(&self, data_len: usize) -> ProgramClientResult<u64>;
+    /// Fetch a recent blockhash
+    fn get_latest_blockhash(&self) -> ProgramClientResult<Hash>;
+    /// Send a signed transaction
+    fn send_transaction(&self, transaction: &Transaction) -> ProgramClientResult<ST::Output>;
+    /// Fetch an account, if it exists
+    fn get_account(&self, address: Pubkey) -> ProgramClientResult<Option<Account>>;
+}
+
+/// Blocking RPC backend, sending and confirming each transaction at the
+/// client's commitment
+impl BlockingProgramClient<ProgramRpcClientSendTransaction> for RpcClient {
+    fn get_minimum_balance_for_rent_exemption(&self, data_len: usize) -> ProgramClientResult<u64> {
+        Ok(self.get_minimum_balance_for_rent_exemption(data_len)?)
+    }
+
+    fn get_latest_blockhash(&self) -> ProgramClientResult<Hash> {
+        Ok(self.get_latest_blockhash()?)
+    }
+
+    fn send_transaction(
+        &self,
+        transaction: &Transaction,
+    ) -> ProgramClientResult<RpcClientResponse> {
+        self.send_and_confirm_transaction(transaction)
+            .map(RpcClientResponse::Signature)
+            .map_err(Into::into)
+    }
+
+    fn get_account(&self, address: Pubkey) -> ProgramClientResult<Option<Account>> {
+        Ok(self
+            .get_account_with_commitment(&address, self.commitment())?
+            .value)
+    }
+}
+
+/// `ProgramClient` over a synchronous backend
+pub struct ProgramBlockingClient<ST> {
+    client: Arc<dyn BlockingProgramClient<ST>>,
+}
+
+impl<ST> ProgramBlockingClient<ST> {
+    /// Wrap a synchronous backend
+    pub fn new(client: Arc<dyn BlockingProgramClient<ST>>) -> Self {
+        Self { client }
+    }
+}
+
+impl<ST> fmt::Debug for ProgramBlockingClient<ST> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProgramBlockingClient").finish()
+    }
+}
+
+#[async_trait]
+impl<ST> ProgramClient<ST> for ProgramBlockingClient<ST>
+where
+    ST: SendTransaction + Send + Sync,
+{
+    async fn get_minimum_balance_for_rent_exemption(
+        &self,
+        data_len: usize,
+    ) -> ProgramClientResult<u64> {
+        self.client.get_minimum_balance_for_rent_exemption(data_len)
+    }
+
+    async fn get_latest_blockhash(&self) -> ProgramClientResult<Hash> {
+        self.client.get_latest_blockhash()
+    }
+
+    async fn send_transaction(&self, transaction: &Transaction) -> ProgramClientResult<ST::Output> {
+        self.client.send_transaction(transaction)
+    }
+
+    async fn get_account(&self, address: Pubkey) -> ProgramClientResult<Option<Account>> {
+        self.client.get_account(address)
+    }
+}
+
+/// Blocking Keyring Program Client
+///
+/// Wraps a `Keyring` and runs each operation to completion before
+/// returning. Operations fail with the same errors as their async
+/// counterparts.
+pub struct BlockingKeyring<T> {
+    keyring: Keyring<T>,
+}
+
+impl<T> fmt::Debug for BlockingKeyring<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("BlockingKeyring")
+            .field(&self.keyring)
+            .finish()
+    }
+}
+
+/// Wrap a configured `Keyring`
+///
+/// Operations are driven by `futures::executor::block_on`, which has no
+/// reactor. The keyring's client, simulator and v0 sender must not need
+/// tokio's: futures from a nonblocking `RpcClient` or a `BanksClient` panic
+/// when polled outside of a runtime. Wrap a `ProgramBlockingClient` instead.
+impl<T> From<Keyring<T>> for BlockingKeyring<T> {
+    fn from(keyring: Keyring<T>) -> Self {
+        Self { keyring }
+    }
+}
+
+impl<T> BlockingKeyring<T>
+where
+    T: SendTransaction + Send + Sync + 'static,
+{
+    /// Create a new blocking client over a synchronous backend
+    ///
+    /// To configure the client further, build a `Keyring` over a
+    /// `ProgramBlockingClient` and convert it with `BlockingKeyring::from`.
+    pub fn new(
+        client: Arc<dyn BlockingProgramClient<T>>,
+        program_id: &Pubkey,
+        payer: Arc<dyn Signer>,
+    ) -> Self {
+        Keyring::new(
+            Arc::new(ProgramBlockingClient::new(client)),
+            program_id,
+            payer,
+        )
+        .into()
+    }
+
+    /// The wrapped async client
+    pub fn keyring(&self) -> &Keyring<T> {
+        &self.keyring
+    }
+
+    /// Get the users's keyring address
+    pub fn get_keyring_address(&self, authority: &Pubkey) -> (Pubkey, u8) {
+        self.keyring.get_keyring_address(authority)
+    }
+
+    /// Fetch the user's keyring account, unpacked
+    pub fn get_keystore(&self, authority: &Pubkey) -> Result<Keystore, KeyringError> {
+        block_on(self.keyring.get_keystore(authority))
+    }
+
+    /// Fetch the keystores of many users
+    pub fn get_keystores(
+        &self,
+        authorities: &[Pubkey],
+    ) -> HashMap<Pubkey, Result<Keystore, KeyringError>> {
+        block_on(self.keyring.get_keystores(authorities))
+    }
+
+    /// Create a new keyring
+    pub fn create_keyring(&self, authority: &dyn Signer) -> Result<T::Output, KeyringError> {
+        block_on(self.keyring.create_keyring(authority))
+    }
+
+    /// Add a new key to a keystore, see `Keyring::add_entry`
+    pub fn add_entry(
+        &self,
+        authority: &dyn Signer,
+        entry: impl Into<KeystoreEntry>,
+    ) -> Result<KeystoreWrite<T::Output>, KeyringError> {
+        block_on(self.keyring.add_entry(authority, entry))
+    }
+
+    /// Remove a key from a keystore, see `Keyring::remove_entry`
+    pub fn remove_entry(
+        &self,
+        authority: &dyn Signer,
+        entry: EncryptionKeyConfig,
+    ) -> Result<KeystoreWrite<T::Output>, KeyringError> {
+        block_on(self.keyring.remove_entry(authority, entry))
+    }
+
+    /// Top up a keyring's rent, see `Keyring::fund_rent`
+    pub fn fund_rent(
+        &self,
+        authority: &Pubkey,
+        new_len: usize,
+    ) -> Result<Option<T::Output>, KeyringError> {
+        block_on(self.keyring.fund_rent(authority, new_len))
+    }
+
+    /// Close a keyring, see `Keyring::close_keyring`
+    pub fn close_keyring(
+        &self,
+        authority: &dyn Signer,
+        destination: &Pubkey,
+    ) -> Result<T::Output, KeyringError> {
+        block_on(self.keyring.close_keyring(authority, destination))
+    }
+}
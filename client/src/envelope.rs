@@ -0,0 +1,267 @@
+//! Multi-recipient envelope encryption
+//!
+//! The message is encrypted once with a random content key, and the content
+//! key is wrapped for every recipient with the best key their keystore
+//! offers. Each wrapped key is tagged with the canonical fingerprint of the
+//! recipient key it was wrapped for, see `EncryptionKeyConfig::fingerprint`,
+//! so a recipient can find its own slot.
+
+use {
+    crate::{
+        error::KeyringError,
+        hpke,
+        keystore::{Curve25519, EncryptionKeyConfig, Fingerprint, Hpke, HpkeConfig},
+        sealed_box,
+    },
+    borsh::{BorshDeserialize, BorshSerialize},
+    chacha20poly1305::{
+        aead::{Aead, NewAead, Payload},
+        Key, XChaCha20Poly1305, XNonce,
+    },
+    rand::{rngs::OsRng, RngCore},
+    x25519_dalek::{PublicKey, StaticSecret},
+};
+
+/// HPKE `info` used when wrapping content keys
+const HPKE_INFO: &[u8] = b"spl-keyring:envelope";
+
+/// Key a recipient's content key is wrapped for
+#[derive(Clone, Debug, PartialEq)]
+pub enum RecipientKey {
+    /// Curve25519 key, wrapped with a sealed box
+    Curve25519(PublicKey),
+    /// HPKE recipient configuration, wrapped with HPKE base mode
+    Hpke(Hpke),
+}
+
+impl RecipientKey {
+    /// Fingerprint identifying the recipient's slot in an envelope: the
+    /// canonical fingerprint of the key as a keystore entry
+    pub fn fingerprint(&self) -> Fingerprint {
+        match self {
+            RecipientKey::Curve25519(key) => curve25519_fingerprint(key),
+            RecipientKey::Hpke(key) => EncryptionKeyConfig::Hpke(key.clone()).fingerprint(),
+        }
+    }
+}
+
+/// Content key wrapped for a single recipient
+#[derive(Clone, Debug, PartialEq, BorshDeserialize, BorshSerialize)]
+pub enum WrappedKey {
+    /// Content key in a sealed box
+    SealedBox(Vec<u8>),
+    /// Content key sealed with HPKE
+    Hpke {
+        /// Cipher suite used
+        config: HpkeConfig,
+        /// Encapsulated key
+        enc: Vec<u8>,
+        /// Encrypted content key
+        ciphertext: Vec<u8>,
+    },
+}
+
+/// Per-recipient header of an envelope
+#[derive(Clone, Debug, PartialEq, BorshDeserialize, BorshSerialize)]
+pub struct RecipientHeader {
+    /// Fingerprint of the recipient key
+    pub fingerprint: Fingerprint,
+    /// The content key, wrapped for that recipient key
+    pub wrapped_key: WrappedKey,
+}
+
+/// A message encrypted for several recipients
+#[derive(Clone, Debug, PartialEq, BorshDeserialize, BorshSerialize)]
+pub struct Envelope {
+    /// One header per recipient
+    pub recipients: Vec<RecipientHeader>,
+    /// Nonce for the content encryption
+    pub nonce: [u8; 24],
+    /// The message, encrypted with the content key
+    pub ciphertext: Vec<u8>,
+}
+
+impl Envelope {
+    /// Encrypt a message for every recipient key
+    ///
+    /// Duplicate recipient keys get a single header.
+    pub fn seal(recipients: &[RecipientKey], plaintext: &[u8]) -> Result<Self, KeyringError> {
+        let mut content_key = [0u8; 32];
+        OsRng.fill_bytes(&mut content_key);
+        let mut nonce = [0u8; 24];
+        OsRng.fill_bytes(&mut nonce);
+
+        let mut headers: Vec<RecipientHeader> = Vec::with_capacity(recipients.len());
+        for recipient in recipients {
+            let fingerprint = recipient.fingerprint();
+            if headers.iter().any(|h| h.fingerprint == fingerprint) {
+                continue;
+            }
+            let wrapped_key = match recipient {
+                RecipientKey::Curve25519(key) => {
+                    WrappedKey::SealedBox(sealed_box::seal(key, &content_key)?)
+                }
+                RecipientKey::Hpke(key) => {
+                    let (enc, ciphertext) =
+                        hpke::seal(key, HPKE_INFO, &fingerprint.0, &content_key)?;
+                    WrappedKey::Hpke {
+                        config: key.config,
+                        enc,
+                        ciphertext,
+                    }
+                }
+            };
+            headers.push(RecipientHeader {
+                fingerprint,
+                wrapped_key,
+            });
+        }
+
+        let ciphertext = XChaCha20Poly1305::new(Key::from_slice(&content_key))
+            .encrypt(
+                XNonce::from_slice(&nonce),
+                Payload {
+                    msg: plaintext,
+                    aad: &recipients_aad(&headers),
+                },
+            )
+            .map_err(|_| KeyringError::EncryptionFailed)?;
+
+        Ok(Self {
+            recipients: headers,
+            nonce,
+            ciphertext,
+        })
+    }
+
+    /// Decrypt the message with the X25519 secret key of one of the
+    /// recipients
+    ///
+    /// The secret key may have been published either as a Curve25519 key or
+    /// in an HPKE configuration.
+    pub fn open(&self, secret: &StaticSecret) -> Result<Vec<u8>, KeyringError> {
+        let public_key = PublicKey::from(secret);
+        let header = self
+            .recipients
+            .iter()
+            .find(|h| h.fingerprint == header_fingerprint(&public_key, &h.wrapped_key))
+            .ok_or(KeyringError::EntryNotFound)?;
+
+        let content_key = match &header.wrapped_key {
+            WrappedKey::SealedBox(sealed) => sealed_box::open(secret, sealed)?,
+            WrappedKey::Hpke {
+                config,
+                enc,
+                ciphertext,
+            } => hpke::open(
+                config,
+                secret,
+                enc,
+                HPKE_INFO,
+                &header.fingerprint.0,
+                ciphertext,
+            )?,
+        };
+        if content_key.len() != 32 {
+            return Err(KeyringError::DecryptionFailed);
+        }
+
+        XChaCha20Poly1305::new(Key::from_slice(&content_key))
+            .decrypt(
+                XNonce::from_slice(&self.nonce),
+                Payload {
+                    msg: &self.ciphertext,
+                    aad: &recipients_aad(&self.recipients),
+                },
+            )
+            .map_err(|_| KeyringError::DecryptionFailed)
+    }
+}
+
+fn curve25519_fingerprint(key: &PublicKey) -> Fingerprint {
+    EncryptionKeyConfig::Curve25519(Curve25519(key.to_bytes())).fingerprint()
+}
+
+/// The fingerprint a header would carry if its content key was wrapped for
+/// `key`
+fn header_fingerprint(key: &PublicKey, wrapped_key: &WrappedKey) -> Fingerprint {
+    match wrapped_key {
+        WrappedKey::SealedBox(_) => curve25519_fingerprint(key),
+        WrappedKey::Hpke { config, .. } => EncryptionKeyConfig::Hpke(Hpke {
+            public_key: key.as_bytes().to_vec(),
+            config: *config,
+        })
+        .fingerprint(),
+    }
+}
+
+/// Binds the recipient list to the content, so headers can't be stripped
+/// or swapped without detection
+fn recipients_aad(headers: &[RecipientHeader]) -> Vec<u8> {
+    headers.iter().flat_map(|h| h.fingerprint.0).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::hpke::{AEAD_CHACHA20_POLY1305, KDF_HKDF_SHA256, KEM_X25519_HKDF_SHA256},
+    };
+
+    #[test]
+    fn test_seal_open() {
+        let alice = StaticSecret::new(OsRng);
+        let bob = StaticSecret::new(OsRng);
+        let carol = StaticSecret::new(OsRng);
+        let bob_key = Hpke {
+            public_key: PublicKey::from(&bob).as_bytes().to_vec(),
+            config: HpkeConfig {
+                kem_id: KEM_X25519_HKDF_SHA256,
+                kdf_id: KDF_HKDF_SHA256,
+                aead_id: AEAD_CHACHA20_POLY1305,
+            },
+        };
+        let recipients = [
+            RecipientKey::Curve25519(PublicKey::from(&alice)),
+            RecipientKey::Hpke(bob_key.clone()),
+            RecipientKey::Curve25519(PublicKey::from(&alice)),
+        ];
+
+        let envelope = Envelope::seal(&recipients, b"hello keyring").unwrap();
+        assert_eq!(envelope.recipients.len(), 2);
+
+        // Headers carry the keys' keystore fingerprints
+        assert_eq!(
+            envelope.recipients[0].fingerprint,
+            EncryptionKeyConfig::Curve25519(Curve25519(PublicKey::from(&alice).to_bytes()))
+                .fingerprint()
+        );
+        assert_eq!(
+            envelope.recipients[1].fingerprint,
+            EncryptionKeyConfig::Hpke(bob_key).fingerprint()
+        );
+        assert_eq!(envelope.open(&alice).unwrap(), b"hello keyring");
+        assert_eq!(envelope.open(&bob).unwrap(), b"hello keyring");
+        assert!(matches!(
+            envelope.open(&carol),
+            Err(KeyringError::EntryNotFound)
+        ));
+    }
+
+    #[test]
+    fn test_stripped_recipient() {
+        let alice = StaticSecret::new(OsRng);
+        let bob = StaticSecret::new(OsRng);
+        let recipients = [
+            RecipientKey::Curve25519(PublicKey::from(&alice)),
+            RecipientKey::Curve25519(PublicKey::from(&bob)),
+        ];
+
+        let mut envelope = Envelope::seal(&recipients, b"hello keyring").unwrap();
+        envelope.recipients.pop();
+        assert!(matches!(
+            envelope.open(&alice),
+            Err(KeyringError::DecryptionFailed)
+        ));
+    }
+}
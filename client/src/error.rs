@@ -1,7 +1,8 @@
 //! Error types for the Keyring Client
 
 use {
-    solana_sdk::program_error::ProgramError, spl_token_client::client::ProgramClientError,
+    solana_sdk::{program_error::ProgramError, transaction::TransactionError},
+    spl_token_client::client::ProgramClientError,
     thiserror::Error,
 };
 
@@ -17,4 +18,63 @@ pub enum KeyringError {
     /// Keystore not found
     #[error("Keystore not found")]
     KeystoreNotFound,
+    /// Keyring account is not owned by the keyring program
+    #[error("Keyring account is not owned by the keyring program")]
+    IncorrectOwner,
+    /// Keyring account does not hold a valid keystore
+    #[error("Invalid keystore data")]
+    InvalidKeystore,
+    /// Keystore does not fit in a single transaction
+    #[error("Keystore too large for a single transaction")]
+    KeystoreTooLarge,
+    /// Keyring doesn't hold the keystore after a chunked write
+    #[error("Keystore write incomplete")]
+    IncompleteWrite,
+    /// Keystore already holds the key
+    #[error("Keystore already holds this key")]
+    DuplicateEntry,
+    /// Transaction signature is missing or invalid
+    #[error("Missing or invalid transaction signature")]
+    InvalidSignature,
+    /// Durable nonce account is missing or uninitialized
+    #[error("Invalid durable nonce account")]
+    InvalidNonceAccount,
+    /// Compute units can't be estimated without a simulator
+    #[error("No transaction simulator configured")]
+    SimulatorNotConfigured,
+    /// Transaction failed in simulation
+    #[error("Simulation failed: {0}")]
+    SimulationFailed(TransactionError),
+    /// Keystore entry not found
+    #[error("Keystore entry not found")]
+    EntryNotFound,
+    /// Keystore entry was already revoked or retired
+    #[error("Keystore entry is not active")]
+    InactiveEntry,
+    /// Recipient publishes no usable key for any algorithm the sender supports
+    #[error("No compatible key")]
+    NoCompatibleKey,
+    /// Key length does not match the algorithm
+    #[error("Invalid key length: expected {expected} bytes, got {actual}")]
+    InvalidKeyLength {
+        /// Length required by the algorithm
+        expected: usize,
+        /// Length of the provided key
+        actual: usize,
+    },
+    /// Entry metadata text is too long
+    #[error("Entry metadata too long")]
+    MetadataTooLong,
+    /// Key is not valid for the algorithm
+    #[error("Invalid key")]
+    InvalidKey,
+    /// Algorithm or cipher suite is not supported by this client
+    #[error("Unsupported algorithm")]
+    UnsupportedAlgorithm,
+    /// Message could not be encrypted
+    #[error("Encryption failed")]
+    EncryptionFailed,
+    /// Ciphertext could not be decrypted
+    #[error("Decryption failed")]
+    DecryptionFailed,
 }
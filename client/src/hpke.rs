@@ -0,0 +1,292 @@
+//! HPKE (RFC 9180) base mode seal and open
+//!
+//! Senders use the cipher suite advertised in the recipient's `Hpke` keystore
+//! entry. The suite currently implemented is DHKEM(X25519, HKDF-SHA256) with
+//! HKDF-SHA256 and ChaCha20-Poly1305; entries advertising any other suite are
+//! rejected with `KeyringError::UnsupportedAlgorithm`.
+
+use {
+    crate::{
+        error::KeyringError,
+        keystore::{Hpke, HpkeConfig},
+    },
+    chacha20poly1305::{
+        aead::{Aead, NewAead, Payload},
+        ChaCha20Poly1305, Key, Nonce,
+    },
+    hkdf::Hkdf,
+    rand::rngs::OsRng,
+    sha2::Sha256,
+    x25519_dalek::{PublicKey, StaticSecret},
+};
+
+/// DHKEM(X25519, HKDF-SHA256)
+pub const KEM_X25519_HKDF_SHA256: u16 = 0x0020;
+/// HKDF-SHA256
+pub const KDF_HKDF_SHA256: u16 = 0x0001;
+/// ChaCha20-Poly1305
+pub const AEAD_CHACHA20_POLY1305: u16 = 0x0003;
+
+/// The only mode implemented: no PSK, no sender authentication
+const MODE_BASE: u8 = 0x00;
+
+/// Encrypt a single message to an HPKE recipient configuration
+///
+/// Returns the encapsulated key and the ciphertext, both of which the
+/// recipient needs to open the message.
+pub fn seal(
+    recipient: &Hpke,
+    info: &[u8],
+    aad: &[u8],
+    plaintext: &[u8],
+) -> Result<(Vec<u8>, Vec<u8>), KeyringError> {
+    seal_with_ephemeral(recipient, &StaticSecret::new(OsRng), info, aad, plaintext)
+}
+
+/// Decrypt a single message sealed to an HPKE recipient configuration
+pub fn open(
+    config: &HpkeConfig,
+    recipient: &StaticSecret,
+    enc: &[u8],
+    info: &[u8],
+    aad: &[u8],
+    ciphertext: &[u8],
+) -> Result<Vec<u8>, KeyringError> {
+    check_suite(config)?;
+    let enc = public_key(enc).map_err(|_| KeyringError::DecryptionFailed)?;
+    let recipient_public = PublicKey::from(recipient);
+
+    let dh = recipient.diffie_hellman(&enc);
+    let shared_secret = extract_and_expand(dh.as_bytes(), &enc, &recipient_public)
+        .map_err(|_| KeyringError::DecryptionFailed)?;
+    let (cipher, nonce) = key_schedule(config, &shared_secret, info);
+
+    cipher
+        .decrypt(
+            &nonce,
+            Payload {
+                msg: ciphertext,
+                aad,
+            },
+        )
+        .map_err(|_| KeyringError::DecryptionFailed)
+}
+
+fn seal_with_ephemeral(
+    recipient: &Hpke,
+    ephemeral: &StaticSecret,
+    info: &[u8],
+    aad: &[u8],
+    plaintext: &[u8],
+) -> Result<(Vec<u8>, Vec<u8>), KeyringError> {
+    check_suite(&recipient.config)?;
+    let recipient_public = public_key(&recipient.public_key)?;
+    let enc = PublicKey::from(ephemeral);
+
+    let dh = ephemeral.diffie_hellman(&recipient_public);
+    let shared_secret = extract_and_expand(dh.as_bytes(), &enc, &recipient_public)?;
+    let (cipher, nonce) = key_schedule(&recipient.config, &shared_secret, info);
+
+    let ciphertext = cipher
+        .encrypt(
+            &nonce,
+            Payload {
+                msg: plaintext,
+                aad,
+            },
+        )
+        .map_err(|_| KeyringError::EncryptionFailed)?;
+    Ok((enc.as_bytes().to_vec(), ciphertext))
+}
+
+fn check_suite(config: &HpkeConfig) -> Result<(), KeyringError> {
+    if config.is_supported() {
+        Ok(())
+    } else {
+        Err(KeyringError::UnsupportedAlgorithm)
+    }
+}
+
+fn public_key(bytes: &[u8]) -> Result<PublicKey, KeyringError> {
+    let bytes: [u8; 32] = bytes
+        .try_into()
+        .map_err(|_| KeyringError::InvalidKeyLength {
+            expected: 32,
+            actual: bytes.len(),
+        })?;
+    Ok(PublicKey::from(bytes))
+}
+
+/// DHKEM `ExtractAndExpand`, producing the KEM shared secret
+fn extract_and_expand(
+    dh: &[u8; 32],
+    enc: &PublicKey,
+    recipient: &PublicKey,
+) -> Result<[u8; 32], KeyringError> {
+    // A low-order key yields an all-zero Diffie-Hellman output
+    if dh == &[0u8; 32] {
+        return Err(KeyringError::InvalidKey);
+    }
+
+    let suite_id = kem_suite_id();
+    let mut kem_context = [0u8; 64];
+    kem_context[..32].copy_from_slice(enc.as_bytes());
+    kem_context[32..].copy_from_slice(recipient.as_bytes());
+
+    let eae_prk = labeled_extract(&suite_id, &[], b"eae_prk", dh);
+    let mut shared_secret = [0u8; 32];
+    labeled_expand(
+        &suite_id,
+        &eae_prk,
+        b"shared_secret",
+        &kem_context,
+        &mut shared_secret,
+    );
+    Ok(shared_secret)
+}
+
+/// Base mode `KeySchedule`, producing the AEAD and the nonce for the first
+/// (and only) message
+fn key_schedule(
+    config: &HpkeConfig,
+    shared_secret: &[u8; 32],
+    info: &[u8],
+) -> (ChaCha20Poly1305, Nonce) {
+    let suite_id = hpke_suite_id(config);
+
+    let psk_id_hash = labeled_extract(&suite_id, &[], b"psk_id_hash", &[]);
+    let info_hash = labeled_extract(&suite_id, &[], b"info_hash", info);
+    let mut context = Vec::with_capacity(65);
+    context.push(MODE_BASE);
+    context.extend_from_slice(&psk_id_hash);
+    context.extend_from_slice(&info_hash);
+
+    let secret = labeled_extract(&suite_id, shared_secret, b"secret", &[]);
+    let mut key = [0u8; 32];
+    labeled_expand(&suite_id, &secret, b"key", &context, &mut key);
+    let mut base_nonce = [0u8; 12];
+    labeled_expand(&suite_id, &secret, b"base_nonce", &context, &mut base_nonce);
+
+    (
+        ChaCha20Poly1305::new(Key::from_slice(&key)),
+        *Nonce::from_slice(&base_nonce),
+    )
+}
+
+fn kem_suite_id() -> Vec<u8> {
+    [b"KEM".as_ref(), &KEM_X25519_HKDF_SHA256.to_be_bytes()].concat()
+}
+
+fn hpke_suite_id(config: &HpkeConfig) -> Vec<u8> {
+    [
+        b"HPKE".as_ref(),
+        &config.kem_id.to_be_bytes(),
+        &config.kdf_id.to_be_bytes(),
+        &config.aead_id.to_be_bytes(),
+    ]
+    .concat()
+}
+
+fn labeled_extract(suite_id: &[u8], salt: &[u8], label: &[u8], ikm: &[u8]) -> [u8; 32] {
+    let labeled_ikm = [b"HPKE-v1".as_ref(), suite_id, label, ikm].concat();
+    let (prk, _) = Hkdf::<Sha256>::extract(Some(salt), &labeled_ikm);
+    prk.into()
+}
+
+fn labeled_expand(suite_id: &[u8], prk: &[u8; 32], label: &[u8], info: &[u8], okm: &mut [u8]) {
+    let len = (okm.len() as u16).to_be_bytes();
+    let labeled_info = [len.as_ref(), b"HPKE-v1", suite_id, label, info].concat();
+    Hkdf::<Sha256>::from_prk(prk)
+        .expect("PRK is the hash length")
+        .expand(&labeled_info, okm)
+        .expect("output is at most 32 bytes");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(hex: &str) -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    fn secret(hex: &str) -> StaticSecret {
+        let bytes: [u8; 32] = decode(hex).try_into().unwrap();
+        StaticSecret::from(bytes)
+    }
+
+    fn config() -> HpkeConfig {
+        HpkeConfig {
+            kem_id: KEM_X25519_HKDF_SHA256,
+            kdf_id: KDF_HKDF_SHA256,
+            aead_id: AEAD_CHACHA20_POLY1305,
+        }
+    }
+
+    #[test]
+    fn test_rfc9180_vector() {
+        // RFC 9180, Appendix A.2.1: DHKEM(X25519, HKDF-SHA256), HKDF-SHA256,
+        // ChaCha20Poly1305, base mode, sequence number 0
+        let info = decode("4f6465206f6e2061204772656369616e2055726e");
+        let sk_e = secret("f4ec9b33b792c372c1d2c2063507b684ef925b8c75a42dbcbf57d63ccd381600");
+        let sk_r = secret("8057991eef8f1f1af18f4a9491d16a1ce333f695d4db8e38da75975c4478e0fb");
+        let recipient = Hpke {
+            public_key: decode("4310ee97d88cc1f088a5576c77ab0cf5c3ac797f3d95139c6c84b5429c59662a"),
+            config: config(),
+        };
+        let aad = decode("436f756e742d30");
+        let pt = decode("4265617574792069732074727574682c20747275746820626561757479");
+
+        let (enc, ct) = seal_with_ephemeral(&recipient, &sk_e, &info, &aad, &pt).unwrap();
+        assert_eq!(
+            enc,
+            decode("1afa08d3dec047a643885163f1180476fa7ddb54c6a8029ea33f95796bf2ac4a")
+        );
+        assert_eq!(
+            ct,
+            decode("1c5250d8034ec2b784ba2cfd69dbdb8af406cfe3ff938e131f0def8c8b60b4db21993c62ce81883d2dd1b51a28")
+        );
+        assert_eq!(open(&config(), &sk_r, &enc, &info, &aad, &ct).unwrap(), pt);
+    }
+
+    #[test]
+    fn test_seal_open() {
+        let sk_r = StaticSecret::new(OsRng);
+        let recipient = Hpke {
+            public_key: PublicKey::from(&sk_r).as_bytes().to_vec(),
+            config: config(),
+        };
+
+        let (enc, ct) = seal(&recipient, b"info", b"aad", b"hello keyring").unwrap();
+        assert_eq!(
+            open(&config(), &sk_r, &enc, b"info", b"aad", &ct).unwrap(),
+            b"hello keyring"
+        );
+        assert!(matches!(
+            open(&config(), &sk_r, &enc, b"other info", b"aad", &ct),
+            Err(KeyringError::DecryptionFailed)
+        ));
+        assert!(matches!(
+            open(&config(), &sk_r, &enc, b"info", b"other aad", &ct),
+            Err(KeyringError::DecryptionFailed)
+        ));
+    }
+
+    #[test]
+    fn test_unsupported_suite() {
+        let recipient = Hpke {
+            public_key: vec![9; 32],
+            config: HpkeConfig {
+                aead_id: 0x0001, // AES-128-GCM
+                ..config()
+            },
+        };
+        assert!(matches!(
+            seal(&recipient, b"", b"", b"hello keyring"),
+            Err(KeyringError::UnsupportedAlgorithm)
+        ));
+    }
+}
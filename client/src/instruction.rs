@@ -0,0 +1,226 @@
+//! Instruction builders for composing keystore changes into other
+//! transactions
+
+use {
+    crate::{
+        error::KeyringError,
+        keyring::{MAX_WRITE_LEN, RENT_TOP_UP_LEN},
+        keystore::{EncryptionKeyConfig, Keystore, KeystoreEntry},
+    },
+    borsh::BorshSerialize,
+    solana_sdk::{instruction::Instruction, pubkey::Pubkey, rent::Rent, system_instruction},
+};
+
+/// Compute units an `UpdateKeyring` instruction needs besides deriving the
+/// keyring address, with headroom for the largest single-transaction write
+const UPDATE_BASE_COMPUTE_UNITS: u32 = 10_000;
+
+/// Compute units of each bump seed tried while deriving the keyring address
+///
+/// The program derives the address with `find_program_address`, which tries
+/// bump seeds from 255 downwards.
+const PDA_BUMP_COMPUTE_UNITS: u32 = 1_500;
+
+/// Compute units of a system program transfer
+const TRANSFER_COMPUTE_UNITS: u32 = 150;
+
+/// The instructions for a keystore change
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeystoreUpdate {
+    /// Instructions to include in the transaction, in order: the rent top-up
+    /// if one is needed, then the update itself
+    pub instructions: Vec<Instruction>,
+    /// Size of the keyring account after the update
+    pub new_len: usize,
+    /// Estimated compute units `instructions` consume
+    ///
+    /// No compute budget instructions are included, since a transaction may
+    /// only hold one of each; callers add this to their own compute-unit
+    /// limit.
+    pub compute_units: u32,
+}
+
+/// Build the instructions that overwrite a keyring with `keystore`
+///
+/// `lamports` is the keyring account's current balance; `payer` tops it up
+/// to the rent-exempt minimum for the new size. The keystore must fit in a
+/// single transaction alongside that top-up, otherwise this fails with
+/// `KeyringError::KeystoreTooLarge`.
+pub fn update_keystore(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    authority: &Pubkey,
+    lamports: u64,
+    rent: &Rent,
+    keystore: &Keystore,
+) -> Result<KeystoreUpdate, KeyringError> {
+    let data = keystore
+        .try_to_vec()
+        .map_err(|e| KeyringError::Program(e.into()))?;
+    if data.len() > MAX_WRITE_LEN {
+        return Err(KeyringError::KeystoreTooLarge);
+    }
+    let new_len = data.len();
+
+    let (keyring, bump_seed) = spl_keyring_program::state::Keyring::pda(program_id, authority);
+    let mut compute_units =
+        UPDATE_BASE_COMPUTE_UNITS + PDA_BUMP_COMPUTE_UNITS * (u32::from(u8::MAX - bump_seed) + 1);
+    let mut instructions = vec![];
+    if let Some(top_up) = rent_top_up(payer, &keyring, lamports, rent.minimum_balance(new_len)) {
+        if new_len > MAX_WRITE_LEN - RENT_TOP_UP_LEN {
+            return Err(KeyringError::KeystoreTooLarge);
+        }
+        instructions.push(top_up);
+        compute_units += TRANSFER_COMPUTE_UNITS;
+    }
+    instructions.push(spl_keyring_program::instruction::update_keyring(
+        program_id, authority, data,
+    )?);
+
+    Ok(KeystoreUpdate {
+        instructions,
+        new_len,
+        compute_units,
+    })
+}
+
+/// The transfer from `payer` topping `keyring` up to `minimum_balance`, if
+/// its `lamports` fall short
+pub(crate) fn rent_top_up(
+    payer: &Pubkey,
+    keyring: &Pubkey,
+    lamports: u64,
+    minimum_balance: u64,
+) -> Option<Instruction> {
+    let shortfall = minimum_balance.saturating_sub(lamports);
+    (shortfall > 0).then(|| system_instruction::transfer(payer, keyring, shortfall))
+}
+
+/// Build the instructions that add an entry to `keystore`
+///
+/// Like `Keyring::add_entry`, adding a key the keystore already holds is a
+/// no-op: the update has no instructions and leaves the size unchanged.
+pub fn add_entry(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    authority: &Pubkey,
+    lamports: u64,
+    rent: &Rent,
+    keystore: &Keystore,
+    entry: impl Into<KeystoreEntry>,
+) -> Result<KeystoreUpdate, KeyringError> {
+    let entry = entry.into();
+    entry.validate()?;
+
+    let mut keystore = keystore.clone();
+    if !keystore.add(entry) {
+        let new_len = keystore
+            .try_to_vec()
+            .map_err(|e| KeyringError::Program(e.into()))?
+            .len();
+        return Ok(KeystoreUpdate {
+            instructions: vec![],
+            new_len,
+            compute_units: 0,
+        });
+    }
+    update_keystore(program_id, payer, authority, lamports, rent, &keystore)
+}
+
+/// Build the instructions that remove a key from `keystore`
+///
+/// Fails with `KeyringError::EntryNotFound` if the keystore doesn't hold the
+/// key.
+pub fn remove_entry(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    authority: &Pubkey,
+    lamports: u64,
+    rent: &Rent,
+    keystore: &Keystore,
+    key: &EncryptionKeyConfig,
+) -> Result<KeystoreUpdate, KeyringError> {
+    let mut keystore = keystore.clone();
+    keystore.remove(key)?;
+    update_keystore(program_id, payer, authority, lamports, rent, &keystore)
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::keystore::Curve25519, solana_sdk::system_program};
+
+    #[test]
+    fn test_add_entry() {
+        let program_id = Pubkey::new_unique();
+        let payer = Pubkey::new_unique();
+        let authority = Pubkey::new_unique();
+        let rent = Rent::default();
+        let key = EncryptionKeyConfig::Curve25519(Curve25519([1; 32]));
+
+        let update = add_entry(
+            &program_id,
+            &payer,
+            &authority,
+            rent.minimum_balance(0),
+            &rent,
+            &Keystore::default(),
+            key.clone(),
+        )
+        .unwrap();
+        let expected = Keystore(vec![key.clone().into()]).try_to_vec().unwrap();
+        assert_eq!(update.new_len, expected.len());
+
+        // Rent top-up, then the update
+        let [transfer, write] = &update.instructions[..] else {
+            panic!("expected two instructions");
+        };
+        assert_eq!(transfer.program_id, system_program::id());
+        assert_eq!(
+            transfer.data,
+            system_instruction::transfer(
+                &payer,
+                &transfer.accounts[1].pubkey,
+                rent.minimum_balance(expected.len()) - rent.minimum_balance(0),
+            )
+            .data
+        );
+        assert_eq!(write.program_id, program_id);
+
+        // Each bump seed tried while deriving the address adds to the estimate
+        let bump_seed = spl_keyring_program::state::Keyring::pda(&program_id, &authority).1;
+        assert_eq!(
+            update.compute_units,
+            UPDATE_BASE_COMPUTE_UNITS
+                + PDA_BUMP_COMPUTE_UNITS * (u32::from(u8::MAX - bump_seed) + 1)
+                + TRANSFER_COMPUTE_UNITS
+        );
+
+        // A funded keyring needs no top-up
+        let update = add_entry(
+            &program_id,
+            &payer,
+            &authority,
+            rent.minimum_balance(expected.len()),
+            &rent,
+            &Keystore::default(),
+            key.clone(),
+        )
+        .unwrap();
+        assert_eq!(update.instructions.len(), 1);
+        assert_eq!(update.instructions[0].program_id, program_id);
+
+        // Adding a key the keystore already holds changes nothing
+        let update = add_entry(
+            &program_id,
+            &payer,
+            &authority,
+            0,
+            &rent,
+            &Keystore(vec![key.clone().into()]),
+            key,
+        )
+        .unwrap();
+        assert!(update.instructions.is_empty());
+        assert_eq!(update.new_len, expected.len());
+    }
+}
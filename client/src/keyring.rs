@@ -2,30 +2,311 @@
 
 use {
     crate::{
+        envelope::Envelope,
         error::KeyringError,
-        keystore::{EncryptionKeyConfig, Keystore},
+        instruction::{self, KeystoreUpdate},
+        keystore::{
+            AeKey, ElGamal, EncryptionKeyConfig, EntryMetadata, Fingerprint, KeyAlgorithm,
+            KeyUsage, Keystore, KeystoreEntry,
+        },
+        reader::KeyringReader,
+        simulation::{self, SimulateTransaction, SimulationReport},
     },
+    async_trait::async_trait,
     borsh::{BorshDeserialize, BorshSerialize},
+    solana_rpc_client::nonblocking::rpc_client::RpcClient,
     solana_sdk::{
-        account::Account, instruction::Instruction, message::Message, pubkey::Pubkey,
-        signature::Keypair, signer::Signer, signers::Signers, transaction::Transaction,
+        account::Account,
+        account_utils::StateMut,
+        address_lookup_table_account::AddressLookupTableAccount,
+        compute_budget::{self, ComputeBudgetInstruction},
+        hash::{Hash, HASH_BYTES},
+        instruction::Instruction,
+        message::{v0, Message, VersionedMessage, MESSAGE_HEADER_LENGTH},
+        nonce,
+        packet::PACKET_DATA_SIZE,
+        program_error::ProgramError,
+        pubkey::{Pubkey, PUBKEY_BYTES},
+        signature::{Signature, SIGNATURE_BYTES},
+        signer::{Signer, SignerError},
+        signers::Signers,
+        system_instruction,
+        transaction::{Transaction, VersionedTransaction},
     },
-    spl_token_client::client::{ProgramClient, SendTransaction},
-    std::{fmt, sync::Arc},
+    spl_token_client::client::{
+        ProgramClient, ProgramClientResult, ProgramRpcClientSendTransaction, RpcClientResponse,
+        SendTransaction,
+    },
+    std::{collections::HashMap, fmt, sync::Arc},
 };
 
+/// Bytes of a transaction writing keystore data, besides the data itself
+///
+/// Covers the largest write transaction the client sends without a durable
+/// nonce: a v0 message signed by a separate payer and authority, holding
+/// both compute budget instructions and a `WriteKeyring` instruction, with
+/// the keyring loaded from a lookup table. Only the keyring can be: signers
+/// and invoked programs stay in the static keys, and loading an address
+/// costs 3 bytes more than listing it.
+const WRITE_TRANSACTION_OVERHEAD: usize = 1 + 2 * SIGNATURE_BYTES // signatures
+    + 1 // version prefix
+    + MESSAGE_HEADER_LENGTH
+    + 1 + 4 * PUBKEY_BYTES // payer, authority and both programs
+    + HASH_BYTES // blockhash
+    + 1 // instruction count
+    + 12 // `SetComputeUnitPrice`
+    + 8 // `SetComputeUnitLimit`
+    + 15 // `WriteKeyring`: program, accounts, data length, len and offset
+    + 1 // address table lookups
+    + PUBKEY_BYTES + 3; // the keyring's table, writable and readonly indexes
+
+/// Largest keystore payload sent in a single transaction
+///
+/// Keystores larger than this are written in chunks of this size with
+/// `WriteKeyring` instructions, one transaction per chunk. Changes that must
+/// be atomic, such as `Keyring::rotate_entry`, are staged instead, see
+/// `MAX_STAGING_WRITE_LEN`.
+pub const MAX_WRITE_LEN: usize = PACKET_DATA_SIZE - WRITE_TRANSACTION_OVERHEAD;
+
+/// Largest keystore payload sent in a single `WriteStaging` transaction
+///
+/// A `WriteStaging` instruction lists the payer and the system program too,
+/// and loads the staging account in place of the keyring.
+pub const MAX_STAGING_WRITE_LEN: usize = MAX_WRITE_LEN
+    - PUBKEY_BYTES // system program
+    - 2; // payer and system program indexes
+
+/// Bytes a rent top-up adds to a write transaction: the system program's
+/// address and the transfer instruction, see `Keyring::write_keystore`
+///
+/// The first transaction of a write that grows the keyring carries the
+/// top-up, so it holds this much less keystore data than `MAX_WRITE_LEN`.
+pub const RENT_TOP_UP_LEN: usize = PUBKEY_BYTES // system program
+    + 17; // program, accounts, data length, and the transfer's tag and lamports
+
+/// Largest compute-unit limit a transaction may request
+pub(crate) const MAX_COMPUTE_UNIT_LIMIT: u32 = 1_400_000;
+
+/// Compute-unit limit requested by the client's transactions
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ComputeUnitLimit {
+    /// Leave the runtime's default limit
+    #[default]
+    Default,
+    /// Simulate each transaction first and request the units it consumed,
+    /// plus some headroom
+    ///
+    /// Requires a simulator, see `Keyring::with_simulator`.
+    Simulated,
+    /// Request a fixed limit
+    Static(u32),
+}
+
+/// Sends v0 transactions, which `ProgramClient` can't
+///
+/// Implemented for the nonblocking `RpcClient`.
+#[async_trait]
+pub trait SendVersionedTransaction<ST>: Send + Sync
+where
+    ST: SendTransaction,
+{
+    /// Send a signed v0 transaction
+    async fn send_versioned_transaction(
+        &self,
+        transaction: &VersionedTransaction,
+    ) -> ProgramClientResult<ST::Output>;
+}
+
+/// Sends and confirms at the client's commitment, like
+/// `ProgramRpcClient` does for legacy transactions
+#[async_trait]
+impl SendVersionedTransaction<ProgramRpcClientSendTransaction> for RpcClient {
+    async fn send_versioned_transaction(
+        &self,
+        transaction: &VersionedTransaction,
+    ) -> ProgramClientResult<RpcClientResponse> {
+        self.send_and_confirm_transaction(transaction)
+            .await
+            .map(RpcClientResponse::Signature)
+            .map_err(Into::into)
+    }
+}
+
+/// Settings for sending v0 transactions
+struct VersionedConfig<ST> {
+    sender: Arc<dyn SendVersionedTransaction<ST>>,
+    lookup_tables: Vec<AddressLookupTableAccount>,
+}
+
+/// Serialized size of a transaction carrying `message`, signatures included
+fn transaction_size(message: &VersionedMessage) -> usize {
+    let signatures = usize::from(message.header().num_required_signatures);
+    1 + signatures * SIGNATURE_BYTES + message.serialize().len()
+}
+
+/// Prepend the instruction advancing a durable nonce, if one is used
+fn with_nonce_advance(
+    instructions: &[Instruction],
+    nonce: Option<&DurableNonce>,
+) -> Vec<Instruction> {
+    nonce
+        .map(|nonce| system_instruction::advance_nonce_account(&nonce.account, &nonce.authority))
+        .into_iter()
+        .chain(instructions.iter().cloned())
+        .collect()
+}
+
+fn unsigned_versioned_transaction(message: VersionedMessage) -> VersionedTransaction {
+    let required = usize::from(message.header().num_required_signatures);
+    VersionedTransaction {
+        signatures: vec![Signature::default(); required],
+        message,
+    }
+}
+
+/// The result of a keystore change
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeystoreWrite<O> {
+    /// Output of each transaction sent, in order
+    ///
+    /// Empty if the change was a no-op. Large keystores are written in
+    /// several transactions.
+    pub outputs: Vec<O>,
+    /// The keystore fetched after the write, if the client refetches
+    pub keystore: Option<Keystore>,
+}
+
+/// A keystore change, as made by the `Keyring` method of the same name
+///
+/// Used to dry-run a change with `Keyring::simulate_change`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum KeystoreChange {
+    /// See `Keyring::add_entry`
+    AddEntry(KeystoreEntry),
+    /// See `Keyring::remove_entry`
+    RemoveEntry(EncryptionKeyConfig),
+    /// See `Keyring::remove_entries`
+    RemoveEntries(Vec<EncryptionKeyConfig>),
+    /// See `Keyring::remove_entry_by_fingerprint`
+    RemoveEntryByFingerprint(Fingerprint),
+    /// See `Keyring::revoke_entry`
+    RevokeEntry(Fingerprint),
+    /// See `Keyring::set_primary`
+    SetPrimary(EncryptionKeyConfig),
+    /// See `Keyring::set_usage`
+    SetUsage(EncryptionKeyConfig, KeyUsage),
+    /// See `Keyring::set_metadata`
+    SetMetadata(EncryptionKeyConfig, EntryMetadata),
+    /// See `Keyring::rotate_entry`
+    RotateEntry {
+        /// The key to retire
+        old: EncryptionKeyConfig,
+        /// The key replacing it
+        new: KeystoreEntry,
+    },
+}
+
+impl KeystoreChange {
+    /// Apply the change to a keystore, failing the way the `Keyring` method
+    /// would
+    ///
+    /// Returns false if the change is a no-op, in which case the `Keyring`
+    /// method sends no transaction.
+    pub fn apply(self, keystore: &mut Keystore) -> Result<bool, KeyringError> {
+        match self {
+            Self::AddEntry(entry) => {
+                entry.validate()?;
+                return Ok(keystore.add(entry));
+            }
+            Self::RemoveEntry(entry) => {
+                keystore.remove(&entry)?;
+            }
+            Self::RemoveEntries(entries) => {
+                let removed = entries
+                    .iter()
+                    .filter(|entry| keystore.remove(entry).is_ok())
+                    .count();
+                return Ok(removed > 0);
+            }
+            Self::RemoveEntryByFingerprint(fingerprint) => {
+                keystore.remove_by_fingerprint(&fingerprint)?;
+            }
+            Self::RevokeEntry(fingerprint) => keystore.revoke(&fingerprint)?,
+            Self::SetPrimary(entry) => keystore.set_primary(&entry)?,
+            Self::SetUsage(entry, usage) => keystore.set_usage(&entry, usage)?,
+            Self::SetMetadata(entry, metadata) => keystore.set_metadata(&entry, metadata)?,
+            Self::RotateEntry { old, new } => {
+                new.validate()?;
+                keystore.rotate(&old, new)?;
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// A durable nonce account used in place of a recent blockhash
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DurableNonce {
+    /// The nonce account
+    pub account: Pubkey,
+    /// The nonce authority, which must sign the transaction
+    pub authority: Pubkey,
+}
+
+/// Attach a signature made elsewhere, such as on an air-gapped device, to a
+/// transaction
+///
+/// Fails with `KeyringError::InvalidSignature` if `signer` is not a
+/// required signer of the transaction or the signature doesn't match its
+/// message.
+pub fn attach_signature(
+    transaction: &mut Transaction,
+    signer: &Pubkey,
+    signature: Signature,
+) -> Result<(), KeyringError> {
+    let position = transaction
+        .get_signing_keypair_positions(&[*signer])
+        .ok()
+        .and_then(|positions| positions[0])
+        .ok_or(KeyringError::InvalidSignature)?;
+    if !signature.verify(signer.as_ref(), &transaction.message_data()) {
+        return Err(KeyringError::InvalidSignature);
+    }
+    transaction.signatures[position] = signature;
+    Ok(())
+}
+
 /// The Keyring Program Client
+///
+/// Keystore changes take the keyring authority as any `Signer`, such as a
+/// hardware wallet or a remote signer. Transaction fees are paid by the
+/// client's own payer, which need not be the authority.
 pub struct Keyring<T> {
-    client: Arc<dyn ProgramClient<T>>,
+    reader: KeyringReader<T>,
     payer: Arc<dyn Signer>,
-    program_id: Pubkey,
+    compute_unit_limit: ComputeUnitLimit,
+    compute_unit_price: Option<u64>,
+    simulator: Option<Arc<dyn SimulateTransaction>>,
+    refetch_after_write: bool,
+    versioned: Option<VersionedConfig<T>>,
 }
 
 impl<T> fmt::Debug for Keyring<T> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("Keyring")
             .field("payer", &self.payer.pubkey())
-            .field("program_id", &self.program_id)
+            .field("program_id", &self.reader.program_id)
+            .field("compute_unit_limit", &self.compute_unit_limit)
+            .field("compute_unit_price", &self.compute_unit_price)
+            .field("simulator", &self.simulator.is_some())
+            .field("refetch_after_write", &self.refetch_after_write)
+            .field(
+                "lookup_tables",
+                &self
+                    .versioned
+                    .as_ref()
+                    .map(|versioned| &versioned.lookup_tables),
+            )
             .finish()
     }
 }
@@ -41,62 +322,402 @@ where
         payer: Arc<dyn Signer>,
     ) -> Self {
         Keyring {
-            client,
+            reader: KeyringReader::new(client, program_id),
             payer,
-            program_id: *program_id,
+            compute_unit_limit: ComputeUnitLimit::default(),
+            compute_unit_price: None,
+            simulator: None,
+            refetch_after_write: false,
+            versioned: None,
         }
     }
 
+    /// Set the compute-unit limit requested by every transaction
+    pub fn with_compute_unit_limit(mut self, compute_unit_limit: ComputeUnitLimit) -> Self {
+        self.compute_unit_limit = compute_unit_limit;
+        self
+    }
+
+    /// Set the priority fee paid by every transaction, in micro-lamports per
+    /// compute unit
+    pub fn with_compute_unit_price(mut self, compute_unit_price: u64) -> Self {
+        self.compute_unit_price = Some(compute_unit_price);
+        self
+    }
+
+    /// Fetch the keystore again after every change and return it
+    ///
+    /// The fetched keystore reflects the write once the client's
+    /// `SendTransaction` waits for confirmation, as
+    /// `ProgramRpcClientSendTransaction` and the banks clients do.
+    pub fn with_refetch_after_write(mut self, refetch_after_write: bool) -> Self {
+        self.refetch_after_write = refetch_after_write;
+        self
+    }
+
+    /// Send v0 transactions through `sender`, loading accounts from the
+    /// given address lookup tables where possible
+    ///
+    /// Every operation then uses v0 messages. Lookup tables are passed
+    /// already resolved; they may be empty.
+    pub fn with_versioned_transactions(
+        mut self,
+        sender: Arc<dyn SendVersionedTransaction<T>>,
+        lookup_tables: Vec<AddressLookupTableAccount>,
+    ) -> Self {
+        self.versioned = Some(VersionedConfig {
+            sender,
+            lookup_tables,
+        });
+        self
+    }
+
+    /// Set the simulator used to estimate compute units
+    pub fn with_simulator(mut self, simulator: Arc<dyn SimulateTransaction>) -> Self {
+        self.simulator = Some(simulator);
+        self
+    }
+
+    /// The read-only client this client queries keyrings with
+    pub fn reader(&self) -> &KeyringReader<T> {
+        &self.reader
+    }
+
     /// Get the users's keyring address
     pub fn get_keyring_address(&self, authority: &Pubkey) -> (Pubkey, u8) {
-        spl_keyring_program::state::Keyring::pda(&self.program_id, authority)
+        self.reader.get_keyring_address(authority)
     }
 
     /// Fetch the user's keyring account
     pub async fn get_keyring_account(&self, authority: &Pubkey) -> Result<Account, KeyringError> {
-        self.client
-            .get_account(self.get_keyring_address(authority).0)
-            .await
-            .map_err(KeyringError::Client)?
-            .ok_or(KeyringError::KeystoreNotFound)
+        self.reader.get_keyring_account(authority).await
     }
 
-    /// Fetch the user's keyring account, unpacked
+    /// Fetch the user's keyring account, unpacked, see
+    /// `KeyringReader::get_keystore`
     pub async fn get_keystore(&self, authority: &Pubkey) -> Result<Keystore, KeyringError> {
-        let keyring_account = self.get_keyring_account(authority).await?;
-        if keyring_account.data.is_empty() {
-            Ok(Keystore::default())
-        } else {
-            Keystore::try_from_slice(&keyring_account.data)
-                .map_err(|e| KeyringError::Program(e.into()))
-        }
+        self.reader.get_keystore(authority).await
     }
 
-    /// Construct a transaction from a list of instructions
-    async fn construct_tx<S: Signers>(
+    /// Fetch the keystores of many users, see `KeyringReader::get_keystores`
+    pub async fn get_keystores(
         &self,
-        keyring_instructions: &[Instruction],
-        signing_keypairs: &S,
+        authorities: &[Pubkey],
+    ) -> HashMap<Pubkey, Result<Keystore, KeyringError>> {
+        self.reader.get_keystores(authorities).await
+    }
+
+    /// Fetch the entries of a user's keystore holding keys of the given
+    /// algorithm
+    pub async fn get_entries_by_algorithm(
+        &self,
+        authority: &Pubkey,
+        algorithm: KeyAlgorithm,
+    ) -> Result<Vec<KeystoreEntry>, KeyringError> {
+        self.reader
+            .get_entries_by_algorithm(authority, algorithm)
+            .await
+    }
+
+    /// Fetch the entry of a user's keystore whose key has the given
+    /// fingerprint
+    pub async fn get_entry_by_fingerprint(
+        &self,
+        authority: &Pubkey,
+        fingerprint: &Fingerprint,
+    ) -> Result<KeystoreEntry, KeyringError> {
+        self.reader
+            .get_entry_by_fingerprint(authority, fingerprint)
+            .await
+    }
+
+    /// Pick the recipient's entry to encrypt to, see
+    /// `KeyringReader::negotiate`
+    pub async fn negotiate(
+        &self,
+        recipient: &Pubkey,
+        supported: &[KeyAlgorithm],
+    ) -> Result<KeystoreEntry, KeyringError> {
+        self.reader.negotiate(recipient, supported).await
+    }
+
+    /// Fetch the ElGamal public key a recipient has published for
+    /// confidential transfers of the given mint
+    pub async fn get_elgamal_pubkey(
+        &self,
+        authority: &Pubkey,
+        mint: &Pubkey,
+    ) -> Result<ElGamal, KeyringError> {
+        self.reader.get_elgamal_pubkey(authority, mint).await
+    }
+
+    /// Fetch the AE key commitment a user has published for confidential
+    /// transfers of the given mint
+    pub async fn get_ae_key(
+        &self,
+        authority: &Pubkey,
+        mint: &Pubkey,
+    ) -> Result<AeKey, KeyringError> {
+        self.reader.get_ae_key(authority, mint).await
+    }
+
+    /// Encrypt a message to a wallet, see `KeyringReader::seal`
+    pub async fn seal(&self, recipient: &Pubkey, message: &[u8]) -> Result<Vec<u8>, KeyringError> {
+        self.reader.seal(recipient, message).await
+    }
+
+    /// Encrypt a message to a wallet with HPKE, see
+    /// `KeyringReader::hpke_seal`
+    pub async fn hpke_seal(
+        &self,
+        recipient: &Pubkey,
+        info: &[u8],
+        aad: &[u8],
+        plaintext: &[u8],
+    ) -> Result<(Vec<u8>, Vec<u8>), KeyringError> {
+        self.reader.hpke_seal(recipient, info, aad, plaintext).await
+    }
+
+    /// Encrypt a message for a group of wallets, see
+    /// `KeyringReader::seal_envelope`
+    pub async fn seal_envelope(
+        &self,
+        recipients: &[Pubkey],
+        plaintext: &[u8],
+    ) -> Result<Envelope, KeyringError> {
+        self.reader.seal_envelope(recipients, plaintext).await
+    }
+
+    /// Build an unsigned transaction from a list of instructions
+    ///
+    /// The client's payer is the fee payer, and the client's compute budget
+    /// settings are applied. With a durable nonce, the transaction first
+    /// advances the nonce and uses its stored blockhash instead of a recent
+    /// one, so it doesn't expire while being signed offline. Signers sign the
+    /// bytes returned by `message_data`.
+    pub async fn build_transaction(
+        &self,
+        instructions: &[Instruction],
+        nonce: Option<&DurableNonce>,
     ) -> Result<Transaction, KeyringError> {
-        let mut instructions = vec![];
-        let payer_key = self.payer.pubkey();
-        let fee_payer = Some(&payer_key);
+        let (instructions, blockhash) = self
+            .prepare_instructions(instructions, nonce, false)
+            .await?;
+        Ok(Transaction::new_unsigned(self.compile_legacy_message(
+            &instructions,
+            nonce,
+            &blockhash,
+        )))
+    }
+
+    /// Build an unsigned v0 transaction from a list of instructions
+    ///
+    /// Like `build_transaction`, but accounts found in the client's address
+    /// lookup tables are loaded from the tables, leaving room for more
+    /// accounts than a legacy message can hold.
+    pub async fn build_versioned_transaction(
+        &self,
+        instructions: &[Instruction],
+        nonce: Option<&DurableNonce>,
+    ) -> Result<VersionedTransaction, KeyringError> {
+        let (instructions, blockhash) =
+            self.prepare_instructions(instructions, nonce, true).await?;
+        let message = self.compile_v0_message(&instructions, nonce, &blockhash)?;
+        Ok(unsigned_versioned_transaction(VersionedMessage::V0(
+            message,
+        )))
+    }
 
-        instructions.extend_from_slice(keyring_instructions);
+    /// Fetch a blockhash and compile a message, compute budget included
+    async fn prepare_message(
+        &self,
+        instructions: &[Instruction],
+        nonce: Option<&DurableNonce>,
+        versioned: bool,
+    ) -> Result<VersionedMessage, KeyringError> {
+        let (instructions, blockhash) = self
+            .prepare_instructions(instructions, nonce, versioned)
+            .await?;
+        self.compile_message(&instructions, nonce, &blockhash, versioned)
+    }
 
-        let (message, blockhash) = {
-            let latest_blockhash = self
+    /// Fetch a blockhash and prepend the client's compute budget
+    /// instructions
+    async fn prepare_instructions(
+        &self,
+        instructions: &[Instruction],
+        nonce: Option<&DurableNonce>,
+        versioned: bool,
+    ) -> Result<(Vec<Instruction>, Hash), KeyringError> {
+        let blockhash = match nonce {
+            Some(nonce) => self.get_nonce_blockhash(&nonce.account).await?,
+            None => self
+                .reader
                 .client
                 .get_latest_blockhash()
                 .await
-                .map_err(KeyringError::Client)?;
-            (
-                Message::new_with_blockhash(&instructions, fee_payer, &latest_blockhash),
-                latest_blockhash,
-            )
+                .map_err(KeyringError::Client)?,
         };
+        let instructions = self
+            .with_compute_budget(instructions, nonce, &blockhash, versioned)
+            .await?;
+        Ok((instructions, blockhash))
+    }
+
+    fn compile_message(
+        &self,
+        instructions: &[Instruction],
+        nonce: Option<&DurableNonce>,
+        blockhash: &Hash,
+        versioned: bool,
+    ) -> Result<VersionedMessage, KeyringError> {
+        if versioned {
+            self.compile_v0_message(instructions, nonce, blockhash)
+                .map(VersionedMessage::V0)
+        } else {
+            Ok(VersionedMessage::Legacy(self.compile_legacy_message(
+                instructions,
+                nonce,
+                blockhash,
+            )))
+        }
+    }
+
+    fn compile_legacy_message(
+        &self,
+        instructions: &[Instruction],
+        nonce: Option<&DurableNonce>,
+        blockhash: &Hash,
+    ) -> Message {
+        Message::new_with_blockhash(
+            &with_nonce_advance(instructions, nonce),
+            Some(&self.payer.pubkey()),
+            blockhash,
+        )
+    }
+
+    fn compile_v0_message(
+        &self,
+        instructions: &[Instruction],
+        nonce: Option<&DurableNonce>,
+        blockhash: &Hash,
+    ) -> Result<v0::Message, KeyringError> {
+        let lookup_tables = self
+            .versioned
+            .as_ref()
+            .map_or(&[][..], |versioned| &versioned.lookup_tables);
+        v0::Message::try_compile(
+            &self.payer.pubkey(),
+            &with_nonce_advance(instructions, nonce),
+            lookup_tables,
+            *blockhash,
+        )
+        .map_err(|error| KeyringError::Client(error.into()))
+    }
+
+    /// Prepend the client's compute budget instructions
+    ///
+    /// A compute-unit limit or price the instructions already set, such as a
+    /// limit covering an `instruction::update_keystore` update, is left
+    /// unchanged; the client only adds the ones that are missing.
+    async fn with_compute_budget(
+        &self,
+        instructions: &[Instruction],
+        nonce: Option<&DurableNonce>,
+        blockhash: &Hash,
+        versioned: bool,
+    ) -> Result<Vec<Instruction>, KeyringError> {
+        let mut has_limit = false;
+        let mut has_price = false;
+        for ix in instructions
+            .iter()
+            .filter(|ix| ix.program_id == compute_budget::id())
+        {
+            match ComputeBudgetInstruction::try_from_slice(&ix.data) {
+                Ok(ComputeBudgetInstruction::SetComputeUnitLimit(_)) => has_limit = true,
+                Ok(ComputeBudgetInstruction::SetComputeUnitPrice(_)) => has_price = true,
+                _ => {}
+            }
+        }
+
+        let mut budget = vec![];
+        if let Some(price) = self.compute_unit_price.filter(|_| !has_price) {
+            budget.push(ComputeBudgetInstruction::set_compute_unit_price(price));
+        }
+        match self.compute_unit_limit {
+            _ if has_limit => {}
+            ComputeUnitLimit::Default => {}
+            ComputeUnitLimit::Static(units) => {
+                budget.push(ComputeBudgetInstruction::set_compute_unit_limit(units));
+            }
+            ComputeUnitLimit::Simulated => {
+                let mut trial = budget.clone();
+                trial.push(ComputeBudgetInstruction::set_compute_unit_limit(
+                    MAX_COMPUTE_UNIT_LIMIT,
+                ));
+                trial.extend_from_slice(instructions);
+                let message = self.compile_message(&trial, nonce, blockhash, versioned)?;
+                let units = self.simulate_compute_units(message).await?;
+                budget.push(ComputeBudgetInstruction::set_compute_unit_limit(units));
+            }
+        }
+        budget.extend_from_slice(instructions);
+        Ok(budget)
+    }
+
+    /// Estimate the compute units a message needs by simulating it, then
+    /// adding 10% headroom
+    ///
+    /// The message should request the maximum compute-unit limit.
+    async fn simulate_compute_units(&self, message: VersionedMessage) -> Result<u32, KeyringError> {
+        let simulator = self
+            .simulator
+            .as_ref()
+            .ok_or(KeyringError::SimulatorNotConfigured)?;
+
+        let result = simulator
+            .simulate_versioned_transaction(&unsigned_versioned_transaction(message))
+            .await
+            .map_err(KeyringError::Client)?;
+        if let Some(err) = result.err {
+            return Err(KeyringError::SimulationFailed(err));
+        }
+        let units = result
+            .units_consumed
+            .saturating_add(result.units_consumed / 10);
+        Ok(
+            u32::try_from(units).map_or(MAX_COMPUTE_UNIT_LIMIT, |units| {
+                units.min(MAX_COMPUTE_UNIT_LIMIT)
+            }),
+        )
+    }
+
+    /// Fetch the blockhash stored in a durable nonce account
+    async fn get_nonce_blockhash(&self, nonce_account: &Pubkey) -> Result<Hash, KeyringError> {
+        let account = self
+            .reader
+            .client
+            .get_account(*nonce_account)
+            .await
+            .map_err(KeyringError::Client)?
+            .ok_or(KeyringError::InvalidNonceAccount)?;
+        let versions: nonce::state::Versions = account
+            .state()
+            .map_err(|_| KeyringError::InvalidNonceAccount)?;
+        match versions.state() {
+            nonce::State::Initialized(data) => Ok(data.blockhash()),
+            nonce::State::Uninitialized => Err(KeyringError::InvalidNonceAccount),
+        }
+    }
 
-        let mut transaction = Transaction::new_unsigned(message);
+    /// Construct a transaction from a list of instructions
+    async fn construct_tx<S: Signers>(
+        &self,
+        keyring_instructions: &[Instruction],
+        signing_keypairs: &S,
+    ) -> Result<Transaction, KeyringError> {
+        let mut transaction = self.build_transaction(keyring_instructions, None).await?;
+        let blockhash = transaction.message.recent_blockhash;
 
         transaction
             .try_partial_sign(&vec![self.payer.clone()], blockhash)
@@ -109,28 +730,157 @@ where
     }
 
     /// Process a transaction from a list of instructions
+    ///
+    /// Sends a v0 transaction if the client is set up for them, see
+    /// `Keyring::with_versioned_transactions`.
     pub async fn process_ixs<S: Signers>(
         &self,
         keyring_instructions: &[Instruction],
         signing_keypairs: &S,
-    ) -> Result<(), KeyringError> {
+    ) -> Result<T::Output, KeyringError> {
+        if let Some(versioned) = &self.versioned {
+            let mut transaction = self
+                .build_versioned_transaction(keyring_instructions, None)
+                .await?;
+            self.sign_versioned(&mut transaction, signing_keypairs)?;
+
+            return versioned
+                .sender
+                .send_versioned_transaction(&transaction)
+                .await
+                .map_err(KeyringError::Client);
+        }
+
         let transaction = self
             .construct_tx(keyring_instructions, signing_keypairs)
             .await?;
 
-        self.client
+        self.reader
+            .client
             .send_transaction(&transaction)
             .await
-            .map_err(KeyringError::Client)?;
+            .map_err(KeyringError::Client)
+    }
 
+    /// Sign a v0 transaction with the payer and the given signers
+    fn sign_versioned<S: Signers>(
+        &self,
+        transaction: &mut VersionedTransaction,
+        signing_keypairs: &S,
+    ) -> Result<(), KeyringError> {
+        let message_data = transaction.message.serialize();
+        let required = usize::from(transaction.message.header().num_required_signatures);
+        let signer_keys = &transaction.message.static_account_keys()[..required];
+
+        let payer = [self.payer.as_ref()];
+        let signatures = payer
+            .try_sign_message(&message_data)
+            .and_then(|mut signatures| {
+                signatures.extend(signing_keypairs.try_sign_message(&message_data)?);
+                Ok(signatures)
+            })
+            .map_err(|error| KeyringError::Client(error.into()))?;
+        let pubkeys = payer
+            .pubkeys()
+            .into_iter()
+            .chain(signing_keypairs.pubkeys());
+
+        for (pubkey, signature) in pubkeys.zip(signatures) {
+            let position = signer_keys
+                .iter()
+                .position(|key| key == &pubkey)
+                .ok_or_else(|| KeyringError::Client(SignerError::KeypairPubkeyMismatch.into()))?;
+            transaction.signatures[position] = signature;
+        }
         Ok(())
     }
 
+    /// Submit a transaction signed elsewhere
+    ///
+    /// The client's payer signs if it is the fee payer and hasn't signed
+    /// yet. Fails with `KeyringError::InvalidSignature`, without sending, if
+    /// any signature is still missing or invalid.
+    pub async fn send_signed_transaction(
+        &self,
+        mut transaction: Transaction,
+    ) -> Result<T::Output, KeyringError> {
+        let payer_key = self.payer.pubkey();
+        if transaction.message.account_keys.first() == Some(&payer_key)
+            && transaction.signatures.first() == Some(&Signature::default())
+        {
+            let blockhash = transaction.message.recent_blockhash;
+            transaction
+                .try_partial_sign(&vec![self.payer.clone()], blockhash)
+                .map_err(|error| KeyringError::Client(error.into()))?;
+        }
+        transaction
+            .verify()
+            .map_err(|_| KeyringError::InvalidSignature)?;
+
+        self.reader
+            .client
+            .send_transaction(&transaction)
+            .await
+            .map_err(KeyringError::Client)
+    }
+
+    /// Build an unsigned transaction that creates a keyring
+    pub async fn create_keyring_transaction(
+        &self,
+        authority: &Pubkey,
+        nonce: Option<&DurableNonce>,
+    ) -> Result<Transaction, KeyringError> {
+        self.build_transaction(
+            &[spl_keyring_program::instruction::create_keyring(
+                &self.reader.program_id,
+                authority,
+            )?],
+            nonce,
+        )
+        .await
+    }
+
+    /// Build an unsigned transaction that overwrites a keyring with the
+    /// given keystore
+    ///
+    /// Any keystore change can be signed offline this way: fetch the
+    /// keystore, apply the change with the `Keystore` methods, and build the
+    /// transaction. The keystore must fit in a single `UpdateKeyring`
+    /// instruction, and the transaction, durable nonce included, in a single
+    /// packet; otherwise this fails with `KeyringError::KeystoreTooLarge`.
+    /// Like `write_keystore`, the transaction tops up the keyring's rent from
+    /// the client's payer if the keystore grows it.
+    pub async fn update_keystore_transaction(
+        &self,
+        authority: &Pubkey,
+        keystore: &Keystore,
+        nonce: Option<&DurableNonce>,
+    ) -> Result<Transaction, KeyringError> {
+        let data = keystore
+            .try_to_vec()
+            .map_err(|e| KeyringError::Program(e.into()))?;
+        let keyring_account = self.get_keyring_account(authority).await?;
+        let transactions = self
+            .write_transactions(authority, keyring_account.lamports, &data)
+            .await?;
+        let [instructions] = &transactions[..] else {
+            return Err(KeyringError::KeystoreTooLarge);
+        };
+
+        let transaction = self.build_transaction(instructions, nonce).await?;
+        if transaction_size(&VersionedMessage::Legacy(transaction.message.clone()))
+            > PACKET_DATA_SIZE
+        {
+            return Err(KeyringError::KeystoreTooLarge);
+        }
+        Ok(transaction)
+    }
+
     /// Create a new keyring
-    pub async fn create_keyring(&self, authority: &Keypair) -> Result<(), KeyringError> {
+    pub async fn create_keyring(&self, authority: &dyn Signer) -> Result<T::Output, KeyringError> {
         self.process_ixs(
             &[spl_keyring_program::instruction::create_keyring(
-                &spl_keyring_program::id(),
+                &self.reader.program_id,
                 &authority.pubkey(),
             )?],
             &[authority],
@@ -138,51 +888,692 @@ where
         .await
     }
 
-    /// Add a new key to a keystore
-    pub async fn add_entry(
+    /// Top up a keyring so it stays rent-exempt at `new_len` bytes
+    ///
+    /// The client's payer transfers the shortfall, so the authority need not
+    /// sign. Returns `None`, without sending a transaction, if the keyring
+    /// already holds enough lamports.
+    pub async fn fund_rent(
         &self,
-        authority: &Keypair,
-        entry: EncryptionKeyConfig,
-    ) -> Result<(), KeyringError> {
-        let mut keystore = self.get_keystore(&authority.pubkey()).await?;
-        keystore.0.push(entry.clone());
+        authority: &Pubkey,
+        new_len: usize,
+    ) -> Result<Option<T::Output>, KeyringError> {
+        let keyring_account = self.get_keyring_account(authority).await?;
+        let lamports = self
+            .reader
+            .client
+            .get_minimum_balance_for_rent_exemption(new_len)
+            .await
+            .map_err(KeyringError::Client)?
+            .saturating_sub(keyring_account.lamports);
+        if lamports == 0 {
+            return Ok(None);
+        }
 
-        let data = keystore
-            .try_to_vec()
-            .map_err(|e| KeyringError::Program(e.into()))?;
+        let signers: [&dyn Signer; 0] = [];
+        self.process_ixs(
+            &[system_instruction::transfer(
+                &self.payer.pubkey(),
+                &self.get_keyring_address(authority).0,
+                lamports,
+            )],
+            &signers,
+        )
+        .await
+        .map(Some)
+    }
+
+    /// Close a keyring, moving its lamports to `destination`
+    ///
+    /// Fails with `KeyringError::KeystoreNotFound`, without sending a
+    /// transaction, if the authority has no keyring. The keyring can be
+    /// created again afterwards.
+    pub async fn close_keyring(
+        &self,
+        authority: &dyn Signer,
+        destination: &Pubkey,
+    ) -> Result<T::Output, KeyringError> {
+        self.get_keyring_account(&authority.pubkey()).await?;
 
         self.process_ixs(
-            &[spl_keyring_program::instruction::update_keyring(
-                &spl_keyring_program::id(),
+            &[spl_keyring_program::instruction::close_keyring(
+                &self.reader.program_id,
                 &authority.pubkey(),
-                data,
+                destination,
             )?],
             &[authority],
         )
         .await
     }
 
+    /// Overwrite the authority's keyring with the given keystore, splitting
+    /// it across several transactions if it is too large for one
+    ///
+    /// If the keystore grows the keyring beyond what its lamports keep
+    /// rent-exempt, the client's payer tops it up in the first transaction.
+    ///
+    /// Chunked writes are not atomic: each chunk lands in its own
+    /// transaction, and until the last one does, the keyring holds a mix of
+    /// old and new bytes that senders can't read. Once every chunk is sent,
+    /// the keyring is read back and compared with the keystore, failing with
+    /// `KeyringError::IncompleteWrite` if it differs, so the client's sends
+    /// must wait for confirmation. After a failed chunked write, call this
+    /// again with the intended keystore to repair the keyring.
+    pub async fn write_keystore(
+        &self,
+        authority: &dyn Signer,
+        keystore: &Keystore,
+    ) -> Result<KeystoreWrite<T::Output>, KeyringError> {
+        let data = keystore
+            .try_to_vec()
+            .map_err(|e| KeyringError::Program(e.into()))?;
+        let keyring_account = self.get_keyring_account(&authority.pubkey()).await?;
+        let transactions = self
+            .write_transactions(&authority.pubkey(), keyring_account.lamports, &data)
+            .await?;
+        let chunked = transactions.len() > 1;
+
+        let mut outputs = vec![];
+        for instructions in transactions {
+            outputs.push(self.process_ixs(&instructions, &[authority]).await?);
+        }
+
+        if chunked {
+            let account = self.get_keyring_account(&authority.pubkey()).await?;
+            if account.data != data {
+                return Err(KeyringError::IncompleteWrite);
+            }
+        }
+
+        self.finish_write(&authority.pubkey(), outputs).await
+    }
+
+    /// The instructions of each transaction writing a serialized keystore: a
+    /// single `UpdateKeyring`, or a `WriteKeyring` per chunk
+    ///
+    /// The first transaction starts with the rent top-up for the new size,
+    /// if the keyring's `lamports` fall short.
+    async fn write_transactions(
+        &self,
+        authority: &Pubkey,
+        lamports: u64,
+        data: &[u8],
+    ) -> Result<Vec<Vec<Instruction>>, KeyringError> {
+        let minimum_balance = self
+            .reader
+            .client
+            .get_minimum_balance_for_rent_exemption(data.len())
+            .await
+            .map_err(KeyringError::Client)?;
+        let mut top_up = instruction::rent_top_up(
+            &self.payer.pubkey(),
+            &self.get_keyring_address(authority).0,
+            lamports,
+            minimum_balance,
+        );
+        let mut chunk_len = if top_up.is_some() {
+            MAX_WRITE_LEN - RENT_TOP_UP_LEN
+        } else {
+            MAX_WRITE_LEN
+        };
+
+        if data.len() <= chunk_len {
+            let update = spl_keyring_program::instruction::update_keyring(
+                &self.reader.program_id,
+                authority,
+                data.to_vec(),
+            )?;
+            return Ok(vec![top_up.into_iter().chain([update]).collect()]);
+        }
+
+        let len = u32::try_from(data.len()).map_err(|_| ProgramError::InvalidAccountData)?;
+        let mut transactions = vec![];
+        let mut offset = 0;
+        while offset < data.len() {
+            let end = data.len().min(offset + chunk_len);
+            let write = spl_keyring_program::instruction::write_keyring(
+                &self.reader.program_id,
+                authority,
+                len,
+                offset as u32,
+                data[offset..end].to_vec(),
+            )?;
+            transactions.push(top_up.take().into_iter().chain([write]).collect());
+            offset = end;
+            chunk_len = MAX_WRITE_LEN;
+        }
+        Ok(transactions)
+    }
+
+    /// Collect the outputs of a keystore write, refetching the keystore in
+    /// refetch mode
+    async fn finish_write(
+        &self,
+        authority: &Pubkey,
+        outputs: Vec<T::Output>,
+    ) -> Result<KeystoreWrite<T::Output>, KeyringError> {
+        let keystore = if self.refetch_after_write {
+            Some(self.get_keystore(authority).await?)
+        } else {
+            None
+        };
+        Ok(KeystoreWrite { outputs, keystore })
+    }
+
+    /// Add a new key to a keystore
+    ///
+    /// Accepts either a bare key, which gets the default configuration, or a
+    /// full `KeystoreEntry`. Adding a key the keystore already holds is a
+    /// no-op and sends no transaction, so retrying is safe.
+    ///
+    /// Like every keystore change, this returns the output of each
+    /// transaction sent.
+    pub async fn add_entry(
+        &self,
+        authority: &dyn Signer,
+        entry: impl Into<KeystoreEntry>,
+    ) -> Result<KeystoreWrite<T::Output>, KeyringError> {
+        let entry = entry.into();
+        entry.validate()?;
+
+        let mut keystore = self.get_keystore(&authority.pubkey()).await?;
+        if !keystore.add(entry) {
+            return self.finish_write(&authority.pubkey(), vec![]).await;
+        }
+
+        self.write_keystore(authority, &keystore).await
+    }
+
     /// Remove a key from a keystore
+    ///
+    /// Fails with `KeyringError::EntryNotFound`, without sending a
+    /// transaction, if the keystore doesn't hold the key.
     pub async fn remove_entry(
         &self,
-        authority: &Keypair,
+        authority: &dyn Signer,
         entry: EncryptionKeyConfig,
-    ) -> Result<(), KeyringError> {
+    ) -> Result<KeystoreWrite<T::Output>, KeyringError> {
         let mut keystore = self.get_keystore(&authority.pubkey()).await?;
-        keystore.0.retain(|e| e != &entry);
+        keystore.remove(&entry)?;
+
+        self.write_keystore(authority, &keystore).await
+    }
+
+    /// Remove several keys from a keystore in one write
+    ///
+    /// Returns the keys the keystore didn't hold, along with the write. If
+    /// none of the keys were present, no transaction is sent.
+    pub async fn remove_entries(
+        &self,
+        authority: &dyn Signer,
+        entries: &[EncryptionKeyConfig],
+    ) -> Result<(Vec<EncryptionKeyConfig>, KeystoreWrite<T::Output>), KeyringError> {
+        let mut keystore = self.get_keystore(&authority.pubkey()).await?;
+        let missing: Vec<_> = entries
+            .iter()
+            .filter(|entry| keystore.remove(entry).is_err())
+            .cloned()
+            .collect();
+
+        let write = if missing.len() < entries.len() {
+            self.write_keystore(authority, &keystore).await?
+        } else {
+            self.finish_write(&authority.pubkey(), vec![]).await?
+        };
+        Ok((missing, write))
+    }
+
+    /// Remove the key with the given fingerprint from a keystore
+    pub async fn remove_entry_by_fingerprint(
+        &self,
+        authority: &dyn Signer,
+        fingerprint: &Fingerprint,
+    ) -> Result<KeystoreWrite<T::Output>, KeyringError> {
+        let mut keystore = self.get_keystore(&authority.pubkey()).await?;
+        keystore.remove_by_fingerprint(fingerprint)?;
+
+        self.write_keystore(authority, &keystore).await
+    }
+
+    /// Revoke the key with the given fingerprint
+    ///
+    /// The entry stays in the keystore so the owner can still decrypt with it,
+    /// but senders stop using it.
+    pub async fn revoke_entry(
+        &self,
+        authority: &dyn Signer,
+        fingerprint: &Fingerprint,
+    ) -> Result<KeystoreWrite<T::Output>, KeyringError> {
+        let mut keystore = self.get_keystore(&authority.pubkey()).await?;
+        keystore.revoke(fingerprint)?;
+
+        self.write_keystore(authority, &keystore).await
+    }
+
+    /// Mark a key as the primary key for its algorithm
+    ///
+    /// Any other key of the same algorithm stops being primary, so rotating
+    /// a key is a matter of adding the new one and marking it primary.
+    pub async fn set_primary(
+        &self,
+        authority: &dyn Signer,
+        entry: &EncryptionKeyConfig,
+    ) -> Result<KeystoreWrite<T::Output>, KeyringError> {
+        let mut keystore = self.get_keystore(&authority.pubkey()).await?;
+        keystore.set_primary(entry)?;
+
+        self.write_keystore(authority, &keystore).await
+    }
+
+    /// Set what a key may be used for
+    pub async fn set_usage(
+        &self,
+        authority: &dyn Signer,
+        entry: &EncryptionKeyConfig,
+        usage: KeyUsage,
+    ) -> Result<KeystoreWrite<T::Output>, KeyringError> {
+        let mut keystore = self.get_keystore(&authority.pubkey()).await?;
+        keystore.set_usage(entry, usage)?;
+
+        self.write_keystore(authority, &keystore).await
+    }
+
+    /// Replace the descriptive metadata of a key
+    pub async fn set_metadata(
+        &self,
+        authority: &dyn Signer,
+        entry: &EncryptionKeyConfig,
+        metadata: EntryMetadata,
+    ) -> Result<KeystoreWrite<T::Output>, KeyringError> {
+        let mut keystore = self.get_keystore(&authority.pubkey()).await?;
+        keystore.set_metadata(entry, metadata)?;
+
+        self.write_keystore(authority, &keystore).await
+    }
+
+    /// Rotate a key: retire `old` and add `new` atomically
+    ///
+    /// Both changes land in the keyring through a single instruction, so the
+    /// keystore never lacks a usable key or offers both. The retired key
+    /// stays in the keystore so the owner can still decrypt earlier messages,
+    /// but senders stop using it.
+    ///
+    /// A rotated keystore that fits in one transaction is written by an
+    /// `UpdateKeyring` instruction. A larger one, such as a keystore holding
+    /// an ML-KEM key, is written into the authority's staging account in
+    /// chunks, then swapped in by a `CommitStaging` instruction; the payer
+    /// funds the staging account until the commit returns its lamports. If a
+    /// chunk fails, the keyring is left untouched and the rotation can be
+    /// retried. Fails with `KeyringError::InactiveEntry` if `old` was already
+    /// revoked or retired.
+    pub async fn rotate_entry(
+        &self,
+        authority: &dyn Signer,
+        old: &EncryptionKeyConfig,
+        new: impl Into<KeystoreEntry>,
+    ) -> Result<KeystoreWrite<T::Output>, KeyringError> {
+        let new = new.into();
+        new.validate()?;
+
+        let keyring_account = self.get_keyring_account(&authority.pubkey()).await?;
+        let mut keystore = self.reader.unpack_keystore(&keyring_account)?;
+        keystore.rotate(old, new)?;
 
         let data = keystore
             .try_to_vec()
             .map_err(|e| KeyringError::Program(e.into()))?;
+        let mut transactions = self
+            .write_transactions(&authority.pubkey(), keyring_account.lamports, &data)
+            .await?;
+        if transactions.len() > 1 {
+            transactions = self.staged_write_transactions(&authority.pubkey(), &data)?;
+        }
 
-        self.process_ixs(
-            &[spl_keyring_program::instruction::update_keyring(
-                &spl_keyring_program::id(),
-                &authority.pubkey(),
-                data,
-            )?],
-            &[authority],
+        let mut outputs = vec![];
+        for instructions in transactions {
+            outputs.push(self.process_ixs(&instructions, &[authority]).await?);
+        }
+        self.finish_write(&authority.pubkey(), outputs).await
+    }
+
+    /// The instructions of each transaction writing a serialized keystore
+    /// atomically: a `WriteStaging` per chunk, then the `CommitStaging`
+    fn staged_write_transactions(
+        &self,
+        authority: &Pubkey,
+        data: &[u8],
+    ) -> Result<Vec<Vec<Instruction>>, KeyringError> {
+        let len = u32::try_from(data.len()).map_err(|_| ProgramError::InvalidAccountData)?;
+        let mut transactions = vec![];
+        for (i, chunk) in data.chunks(MAX_STAGING_WRITE_LEN).enumerate() {
+            transactions.push(vec![spl_keyring_program::instruction::write_staging(
+                &self.reader.program_id,
+                authority,
+                &self.payer.pubkey(),
+                len,
+                (i * MAX_STAGING_WRITE_LEN) as u32,
+                chunk.to_vec(),
+            )?]);
+        }
+        transactions.push(vec![spl_keyring_program::instruction::commit_staging(
+            &self.reader.program_id,
+            authority,
+            &self.payer.pubkey(),
+        )?]);
+        Ok(transactions)
+    }
+
+    /// Dry-run `create_keyring`, see `simulate_change`
+    pub async fn simulate_create_keyring(
+        &self,
+        authority: &Pubkey,
+    ) -> Result<SimulationReport, KeyringError> {
+        let instruction =
+            spl_keyring_program::instruction::create_keyring(&self.reader.program_id, authority)?;
+        self.simulate_transactions(vec![vec![instruction]], 0, 0, 0)
+            .await
+    }
+
+    /// Dry-run `close_keyring`, see `simulate_change`
+    pub async fn simulate_close_keyring(
+        &self,
+        authority: &Pubkey,
+        destination: &Pubkey,
+    ) -> Result<SimulationReport, KeyringError> {
+        let keyring_account = self.get_keyring_account(authority).await?;
+        let instruction = spl_keyring_program::instruction::close_keyring(
+            &self.reader.program_id,
+            authority,
+            destination,
+        )?;
+        self.simulate_transactions(
+            vec![vec![instruction]],
+            keyring_account.data.len(),
+            0,
+            keyring_account.lamports,
+        )
+        .await
+    }
+
+    /// Dry-run an update from the `instruction` builders, such as
+    /// `instruction::add_entry`, see `simulate_change`
+    ///
+    /// All of the update's instructions, the rent top-up included, go in one
+    /// transaction, with the client's compute budget added.
+    pub async fn simulate_keystore_update(
+        &self,
+        authority: &Pubkey,
+        update: &KeystoreUpdate,
+    ) -> Result<SimulationReport, KeyringError> {
+        let keyring_account = self.get_keyring_account(authority).await?;
+        let len_before = keyring_account.data.len();
+        if update.instructions.is_empty() {
+            return Ok(SimulationReport {
+                len_before,
+                len_after: len_before,
+                ..SimulationReport::default()
+            });
+        }
+        self.simulate_transactions(
+            vec![update.instructions.clone()],
+            len_before,
+            update.new_len,
+            keyring_account.lamports,
         )
         .await
     }
+
+    /// Dry-run a keystore change
+    ///
+    /// Builds the transactions the matching `Keyring` method would send and
+    /// runs them through the client's simulator, so a wallet can show the
+    /// fee, rent and compute units before the user confirms. Errors the
+    /// method would return before sending, such as
+    /// `KeyringError::EntryNotFound`, are returned the same way; errors the
+    /// program would return are in the report. Requires a simulator, see
+    /// `Keyring::with_simulator`.
+    pub async fn simulate_change(
+        &self,
+        authority: &Pubkey,
+        change: KeystoreChange,
+    ) -> Result<SimulationReport, KeyringError> {
+        let keyring_account = self.get_keyring_account(authority).await?;
+        let mut keystore = self.reader.unpack_keystore(&keyring_account)?;
+        let len_before = keyring_account.data.len();
+
+        let atomic = matches!(change, KeystoreChange::RotateEntry { .. });
+        if !change.apply(&mut keystore)? {
+            return Ok(SimulationReport {
+                len_before,
+                len_after: len_before,
+                ..SimulationReport::default()
+            });
+        }
+
+        let data = keystore
+            .try_to_vec()
+            .map_err(|e| KeyringError::Program(e.into()))?;
+        let mut transactions = self
+            .write_transactions(authority, keyring_account.lamports, &data)
+            .await?;
+        if !atomic || transactions.len() == 1 {
+            return self
+                .simulate_transactions(
+                    transactions,
+                    len_before,
+                    data.len(),
+                    keyring_account.lamports,
+                )
+                .await;
+        }
+
+        // The commit reads the staged data, so only the staging writes can
+        // run against the current state
+        transactions = self.staged_write_transactions(authority, &data)?;
+        let commit = transactions
+            .pop()
+            .expect("a staged write ends with its commit");
+        let mut report = self
+            .simulate_transactions(
+                transactions,
+                len_before,
+                data.len(),
+                keyring_account.lamports,
+            )
+            .await?;
+        report.transactions += 1;
+        report.fee = report
+            .fee
+            .saturating_add(self.estimate_unsimulated_fee(&commit).await?);
+        Ok(report)
+    }
+
+    /// Estimate the fee of a transaction that can't be simulated yet, with
+    /// the client's compute-unit price and, unless it is static, the default
+    /// compute-unit limit
+    async fn estimate_unsimulated_fee(
+        &self,
+        instructions: &[Instruction],
+    ) -> Result<u64, KeyringError> {
+        let blockhash = self
+            .reader
+            .client
+            .get_latest_blockhash()
+            .await
+            .map_err(KeyringError::Client)?;
+        let mut budget = vec![];
+        if let Some(price) = self.compute_unit_price {
+            budget.push(ComputeBudgetInstruction::set_compute_unit_price(price));
+        }
+        if let ComputeUnitLimit::Static(units) = self.compute_unit_limit {
+            budget.push(ComputeBudgetInstruction::set_compute_unit_limit(units));
+        }
+        budget.extend_from_slice(instructions);
+        let message = self.compile_message(&budget, None, &blockhash, self.versioned.is_some())?;
+        Ok(simulation::estimate_fee(&message))
+    }
+
+    /// Simulate each list of instructions as its own transaction, as
+    /// `process_ixs` would send it
+    async fn simulate_transactions(
+        &self,
+        transactions: Vec<Vec<Instruction>>,
+        len_before: usize,
+        len_after: usize,
+        lamports: u64,
+    ) -> Result<SimulationReport, KeyringError> {
+        let simulator = self
+            .simulator
+            .as_ref()
+            .ok_or(KeyringError::SimulatorNotConfigured)?;
+
+        let rent = self
+            .reader
+            .client
+            .get_minimum_balance_for_rent_exemption(len_after)
+            .await
+            .map_err(KeyringError::Client)?
+            .saturating_sub(lamports);
+        let mut report = SimulationReport {
+            transactions: transactions.len(),
+            len_before,
+            len_after,
+            rent,
+            ..SimulationReport::default()
+        };
+
+        for instructions in transactions {
+            let message = match self
+                .prepare_message(&instructions, None, self.versioned.is_some())
+                .await
+            {
+                Ok(message) => message,
+                // Estimating the compute-unit limit already failed the simulation
+                Err(KeyringError::SimulationFailed(err)) => {
+                    report.err.get_or_insert(err);
+                    continue;
+                }
+                Err(err) => return Err(err),
+            };
+            report.fee = report
+                .fee
+                .saturating_add(simulation::estimate_fee(&message));
+
+            let result = simulator
+                .simulate_versioned_transaction(&unsigned_versioned_transaction(message))
+                .await
+                .map_err(KeyringError::Client)?;
+            report.logs.extend(result.logs);
+            report.units_consumed = report.units_consumed.saturating_add(result.units_consumed);
+            if let Some(err) = result.err {
+                report.err.get_or_insert(err);
+            }
+        }
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_max_write_fits_in_packet() {
+        let payer = Pubkey::new_unique();
+        let authority = Pubkey::new_unique();
+        let lookup_tables = [AddressLookupTableAccount {
+            key: Pubkey::new_unique(),
+            addresses: vec![
+                Pubkey::new_unique(),
+                spl_keyring_program::state::Keyring::pda(&spl_keyring_program::id(), &authority).0,
+            ],
+        }];
+        let instructions = [
+            ComputeBudgetInstruction::set_compute_unit_price(u64::MAX),
+            ComputeBudgetInstruction::set_compute_unit_limit(MAX_COMPUTE_UNIT_LIMIT),
+            spl_keyring_program::instruction::write_keyring(
+                &spl_keyring_program::id(),
+                &authority,
+                u32::MAX,
+                u32::MAX,
+                vec![7; MAX_WRITE_LEN],
+            )
+            .unwrap(),
+        ];
+        let message = VersionedMessage::V0(
+            v0::Message::try_compile(&payer, &instructions, &lookup_tables, Hash::default())
+                .unwrap(),
+        );
+        assert_eq!(message.address_table_lookups().map(<[_]>::len), Some(1));
+        assert_eq!(transaction_size(&message), PACKET_DATA_SIZE);
+        // The README quotes the resulting limit
+        assert_eq!(MAX_WRITE_LEN, 866);
+
+        // Listing the keyring instead is smaller
+        let message = VersionedMessage::V0(
+            v0::Message::try_compile(&payer, &instructions, &[], Hash::default()).unwrap(),
+        );
+        assert!(transaction_size(&message) < PACKET_DATA_SIZE);
+
+        // The legacy encoding is smaller
+        let message = VersionedMessage::Legacy(Message::new_with_blockhash(
+            &instructions,
+            Some(&payer),
+            &Hash::default(),
+        ));
+        assert!(transaction_size(&message) < PACKET_DATA_SIZE);
+
+        // A first chunk carrying a rent top-up holds less data
+        let keyring =
+            spl_keyring_program::state::Keyring::pda(&spl_keyring_program::id(), &authority).0;
+        let instructions = [
+            ComputeBudgetInstruction::set_compute_unit_price(u64::MAX),
+            ComputeBudgetInstruction::set_compute_unit_limit(MAX_COMPUTE_UNIT_LIMIT),
+            system_instruction::transfer(&payer, &keyring, u64::MAX),
+            spl_keyring_program::instruction::write_keyring(
+                &spl_keyring_program::id(),
+                &authority,
+                u32::MAX,
+                u32::MAX,
+                vec![7; MAX_WRITE_LEN - RENT_TOP_UP_LEN],
+            )
+            .unwrap(),
+        ];
+        let message = VersionedMessage::V0(
+            v0::Message::try_compile(&payer, &instructions, &lookup_tables, Hash::default())
+                .unwrap(),
+        );
+        assert_eq!(transaction_size(&message), PACKET_DATA_SIZE);
+
+        // So does a staging write, with the staging account in a table
+        let lookup_tables = [AddressLookupTableAccount {
+            key: Pubkey::new_unique(),
+            addresses: vec![
+                spl_keyring_program::state::KeyringStaging::pda(
+                    &spl_keyring_program::id(),
+                    &authority,
+                )
+                .0,
+            ],
+        }];
+        let instructions = [
+            ComputeBudgetInstruction::set_compute_unit_price(u64::MAX),
+            ComputeBudgetInstruction::set_compute_unit_limit(MAX_COMPUTE_UNIT_LIMIT),
+            spl_keyring_program::instruction::write_staging(
+                &spl_keyring_program::id(),
+                &authority,
+                &payer,
+                u32::MAX,
+                u32::MAX,
+                vec![7; MAX_STAGING_WRITE_LEN],
+            )
+            .unwrap(),
+        ];
+        let message = VersionedMessage::V0(
+            v0::Message::try_compile(&payer, &instructions, &lookup_tables, Hash::default())
+                .unwrap(),
+        );
+        assert_eq!(message.address_table_lookups().map(<[_]>::len), Some(1));
+        assert_eq!(transaction_size(&message), PACKET_DATA_SIZE);
+    }
 }
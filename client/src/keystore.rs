@@ -1,10 +1,601 @@
 //! Types for managing the nested TLV structure of the keystore entry data
 
-use borsh::{BorshDeserialize, BorshSerialize};
+use {
+    crate::{envelope::RecipientKey, error::KeyringError, hpke, x25519},
+    borsh::{BorshDeserialize, BorshSerialize},
+    solana_sdk::{
+        clock::{Slot, UnixTimestamp},
+        hash::hashv,
+        keccak,
+        pubkey::Pubkey,
+    },
+    std::{fmt, io},
+};
 
 /// A keystore
+///
+/// Serialized as `Keystore::VERSION_MARKER`, the layout version, then the
+/// entries. Keystores written before the layout was versioned are a bare
+/// list of keys; they still deserialize, each key getting the default
+/// configuration.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Keystore(pub Vec<KeystoreEntry>);
+
+impl Keystore {
+    /// First byte of a versioned keystore
+    ///
+    /// Unversioned keystores start with a little-endian `u32` key count. Its
+    /// first byte is only `0xff` for 255 keys or more, many more than fit in
+    /// the single transaction those keystores were written with.
+    pub const VERSION_MARKER: u8 = 0xff;
+    /// Current layout version
+    pub const VERSION: u8 = 1;
+
+    /// Pick the entry to encrypt to, given the algorithms the sender supports
+    /// in order of preference
+    ///
+    /// Among the usable entries for the most preferred algorithm, the owner's
+    /// primary entry wins, then the most recently added one; see
+    /// `Keystore::usable_entries`. Algorithms that share a key type, see
+    /// `KeyAlgorithm::is_compatible_with`, stand in for each other when the
+    /// keystore holds no entry of the exact algorithm, so a sender asking for
+    /// Curve25519 may get a `WalletDerivedCurve25519` entry, whose key comes
+    /// from `x25519::public_key_from_wallet`.
+    pub fn negotiate(
+        &self,
+        supported: &[KeyAlgorithm],
+        now: UnixTimestamp,
+    ) -> Result<&KeystoreEntry, KeyringError> {
+        supported
+            .iter()
+            .find_map(|algorithm| {
+                self.usable_entries(now)
+                    .find(|e| e.key.algorithm() == *algorithm)
+                    .or_else(|| {
+                        self.usable_entries(now)
+                            .find(|e| e.key.algorithm().is_compatible_with(*algorithm))
+                    })
+            })
+            .ok_or(KeyringError::NoCompatibleKey)
+    }
+
+    /// The entries senders may encrypt to at `now`, in order of preference:
+    /// primary entries first, then the rest, each newest first
+    ///
+    /// Revoked, retired and expired entries are skipped, as are entries the
+    /// owner restricted to uses other than encryption or key agreement.
+    pub fn usable_entries(&self, now: UnixTimestamp) -> impl Iterator<Item = &KeystoreEntry> {
+        let usable = self.0.iter().rev().filter(move |e| {
+            e.is_usable(now)
+                && (e.config.usage.permits(KeyUsage::ENCRYPTION)
+                    || e.config.usage.permits(KeyUsage::KEY_AGREEMENT))
+        });
+        usable
+            .clone()
+            .filter(|e| e.config.primary)
+            .chain(usable.filter(|e| !e.config.primary))
+    }
+
+    /// Add an entry unless the keystore already holds its key
+    ///
+    /// Returns whether the entry was added. Entries are compared by key
+    /// fingerprint, so an entry with the same key but a different
+    /// configuration is not added either. A primary entry takes the flag
+    /// from every other entry of its algorithm, see `Keystore::set_primary`.
+    pub fn add(&mut self, entry: KeystoreEntry) -> bool {
+        if self.find_by_fingerprint(&entry.key.fingerprint()).is_some() {
+            return false;
+        }
+        let primary = entry.config.primary.then(|| entry.key.clone());
+        self.0.push(entry);
+        if let Some(key) = primary {
+            self.set_primary(&key).expect("the entry was just added");
+        }
+        true
+    }
+
+    /// Remove the entry holding `key`, returning it
+    pub fn remove(&mut self, key: &EncryptionKeyConfig) -> Result<KeystoreEntry, KeyringError> {
+        let index = self
+            .0
+            .iter()
+            .position(|e| &e.key == key)
+            .ok_or(KeyringError::EntryNotFound)?;
+        Ok(self.0.remove(index))
+    }
+
+    /// Mark the entry holding `key` as the primary entry for its algorithm,
+    /// clearing the flag on every other entry of that algorithm
+    pub fn set_primary(&mut self, key: &EncryptionKeyConfig) -> Result<(), KeyringError> {
+        if !self.0.iter().any(|e| &e.key == key) {
+            return Err(KeyringError::EntryNotFound);
+        }
+        let algorithm = key.algorithm();
+        for entry in self.0.iter_mut() {
+            if entry.key.algorithm() == algorithm {
+                entry.config.primary = &entry.key == key;
+            }
+        }
+        Ok(())
+    }
+
+    /// Set the allowed uses of the entry holding `key`
+    pub fn set_usage(
+        &mut self,
+        key: &EncryptionKeyConfig,
+        usage: KeyUsage,
+    ) -> Result<(), KeyringError> {
+        let entry = self
+            .0
+            .iter_mut()
+            .find(|e| &e.key == key)
+            .ok_or(KeyringError::EntryNotFound)?;
+        entry.config.usage = usage;
+        Ok(())
+    }
+
+    /// Retire the entry holding `old` and add `new` in its place
+    ///
+    /// If `old` was the primary entry for its algorithm, or `new` is marked
+    /// primary, `new` becomes primary for its own algorithm, see
+    /// `Keystore::set_primary`. Fails if the keystore already holds the new
+    /// key, or if `old` was already revoked or retired.
+    pub fn rotate(
+        &mut self,
+        old: &EncryptionKeyConfig,
+        new: KeystoreEntry,
+    ) -> Result<(), KeyringError> {
+        if self.find_by_fingerprint(&new.key.fingerprint()).is_some() {
+            return Err(KeyringError::DuplicateEntry);
+        }
+        let old_entry = self
+            .0
+            .iter_mut()
+            .find(|e| &e.key == old)
+            .ok_or(KeyringError::EntryNotFound)?;
+        if old_entry.config.status != KeyStatus::Active {
+            return Err(KeyringError::InactiveEntry);
+        }
+        old_entry.config.status = KeyStatus::Retired;
+        let was_primary = std::mem::take(&mut old_entry.config.primary);
+
+        let new_key = new.key.clone();
+        let primary = was_primary || new.config.primary;
+        self.0.push(new);
+        if primary {
+            self.set_primary(&new_key)?;
+        }
+        Ok(())
+    }
+
+    /// The entries holding keys of the given algorithm, in keystore order
+    pub fn entries_by_algorithm(
+        &self,
+        algorithm: KeyAlgorithm,
+    ) -> impl Iterator<Item = &KeystoreEntry> {
+        self.0
+            .iter()
+            .filter(move |e| e.key.algorithm() == algorithm)
+    }
+
+    /// Find the entry whose key has the given fingerprint
+    pub fn find_by_fingerprint(&self, fingerprint: &Fingerprint) -> Option<&KeystoreEntry> {
+        self.0.iter().find(|e| &e.key.fingerprint() == fingerprint)
+    }
+
+    /// Remove the entry whose key has the given fingerprint, returning it
+    pub fn remove_by_fingerprint(
+        &mut self,
+        fingerprint: &Fingerprint,
+    ) -> Result<KeystoreEntry, KeyringError> {
+        let index = self
+            .0
+            .iter()
+            .position(|e| &e.key.fingerprint() == fingerprint)
+            .ok_or(KeyringError::EntryNotFound)?;
+        Ok(self.0.remove(index))
+    }
+
+    /// Revoke the entry whose key has the given fingerprint
+    ///
+    /// The entry stays in the keystore, but senders stop using it.
+    pub fn revoke(&mut self, fingerprint: &Fingerprint) -> Result<(), KeyringError> {
+        let entry = self
+            .0
+            .iter_mut()
+            .find(|e| &e.key.fingerprint() == fingerprint)
+            .ok_or(KeyringError::EntryNotFound)?;
+        entry.config.status = KeyStatus::Revoked;
+        entry.config.primary = false;
+        Ok(())
+    }
+
+    /// Replace the metadata of the entry holding `key`
+    pub fn set_metadata(
+        &mut self,
+        key: &EncryptionKeyConfig,
+        metadata: EntryMetadata,
+    ) -> Result<(), KeyringError> {
+        metadata.validate()?;
+        let entry = self
+            .0
+            .iter_mut()
+            .find(|e| &e.key == key)
+            .ok_or(KeyringError::EntryNotFound)?;
+        entry.config.metadata = metadata;
+        Ok(())
+    }
+
+    /// Find the ElGamal public key to use for confidential transfers of the
+    /// given mint
+    ///
+    /// Keys tagged with `mint` take precedence over untagged keys; otherwise
+    /// entries are preferred as in `Keystore::usable_entries`.
+    pub fn elgamal_pubkey(&self, mint: &Pubkey, now: UnixTimestamp) -> Option<&ElGamal> {
+        self.find_for_mint(mint, now, |entry| match entry {
+            EncryptionKeyConfig::ElGamal(key) => Some((key, key.mint)),
+            _ => None,
+        })
+    }
+
+    /// Find the AE key commitment to use for confidential transfers of the
+    /// given mint
+    ///
+    /// Commitments tagged with `mint` take precedence over untagged ones;
+    /// otherwise entries are preferred as in `Keystore::usable_entries`.
+    pub fn ae_key(&self, mint: &Pubkey, now: UnixTimestamp) -> Option<&AeKey> {
+        self.find_for_mint(mint, now, |entry| match entry {
+            EncryptionKeyConfig::AeKey(key) => Some((key, key.mint)),
+            _ => None,
+        })
+    }
+
+    fn find_for_mint<'a, T>(
+        &'a self,
+        mint: &Pubkey,
+        now: UnixTimestamp,
+        select: impl Fn(&'a EncryptionKeyConfig) -> Option<(&'a T, Option<Pubkey>)>,
+    ) -> Option<&'a T> {
+        let mut untagged = None;
+        for (key, key_mint) in self
+            .usable_entries(now)
+            .filter_map(|entry| select(&entry.key))
+        {
+            match key_mint {
+                Some(key_mint) if &key_mint == mint => return Some(key),
+                None if untagged.is_none() => untagged = Some(key),
+                _ => {}
+            }
+        }
+        untagged
+    }
+
+    /// Find the preferred usable HPKE configuration with a cipher suite this
+    /// client supports
+    pub fn hpke(&self, now: UnixTimestamp) -> Option<&Hpke> {
+        self.usable_entries(now).find_map(|entry| match &entry.key {
+            EncryptionKeyConfig::Hpke(key) if key.config.is_supported() => Some(key),
+            _ => None,
+        })
+    }
+
+    /// Find the best key to wrap an envelope content key for the wallet owning
+    /// this keystore
+    ///
+    /// Supported HPKE configurations take precedence over Curve25519 keys.
+    pub fn envelope_key(&self, wallet: &Pubkey, now: UnixTimestamp) -> Option<RecipientKey> {
+        self.hpke(now).cloned().map(RecipientKey::Hpke).or_else(|| {
+            self.curve25519_key(wallet, now)
+                .map(RecipientKey::Curve25519)
+        })
+    }
+
+    /// Find the X25519 public key to encrypt to for the wallet owning this
+    /// keystore
+    ///
+    /// Published Curve25519 keys take precedence over the wallet-derived key;
+    /// otherwise entries are preferred as in `Keystore::usable_entries`.
+    pub fn curve25519_key(
+        &self,
+        wallet: &Pubkey,
+        now: UnixTimestamp,
+    ) -> Option<x25519_dalek::PublicKey> {
+        let mut wallet_derived = false;
+        for entry in self.usable_entries(now) {
+            match &entry.key {
+                EncryptionKeyConfig::Curve25519(key) => {
+                    return Some(x25519_dalek::PublicKey::from(key.0))
+                }
+                EncryptionKeyConfig::WalletDerivedCurve25519 => wallet_derived = true,
+                _ => {}
+            }
+        }
+        if wallet_derived {
+            x25519::public_key_from_wallet(wallet).ok()
+        } else {
+            None
+        }
+    }
+}
+
+/// A keystore entry: an encryption key and its configuration
+///
+/// The key and the configuration are each serialized with a `u32` length
+/// prefix, so the program can tell entries apart without knowing the
+/// algorithms and reject duplicate keys. Clients have no such fallback: a
+/// keystore holding an entry whose algorithm this client doesn't recognize
+/// fails to deserialize as a whole, so adding an algorithm requires senders
+/// to upgrade before owners publish keys of it.
+#[derive(Clone, PartialEq)]
+pub struct KeystoreEntry {
+    /// The encryption key
+    pub key: EncryptionKeyConfig,
+    /// Additional configuration for the entry
+    pub config: EntryConfig,
+}
+
+impl KeystoreEntry {
+    /// Create an active entry with the default configuration
+    pub fn new(key: EncryptionKeyConfig) -> Self {
+        Self {
+            key,
+            config: EntryConfig::default(),
+        }
+    }
+
+    /// Check that the key and its metadata are well-formed
+    pub fn validate(&self) -> Result<(), KeyringError> {
+        self.key.validate()?;
+        self.config.metadata.validate()
+    }
+
+    /// Whether senders may encrypt to this key at the given time
+    pub fn is_usable(&self, now: UnixTimestamp) -> bool {
+        self.config.status == KeyStatus::Active
+            && match self.config.expires_at {
+                Some(expires_at) => now < expires_at,
+                None => true,
+            }
+    }
+}
+
+impl From<EncryptionKeyConfig> for KeystoreEntry {
+    fn from(key: EncryptionKeyConfig) -> Self {
+        Self::new(key)
+    }
+}
+
+impl BorshSerialize for Keystore {
+    fn serialize<W: io::Write>(&self, writer: &mut W) -> io::Result<()> {
+        Self::VERSION_MARKER.serialize(writer)?;
+        Self::VERSION.serialize(writer)?;
+        self.0.serialize(writer)
+    }
+}
+
+impl BorshDeserialize for Keystore {
+    fn deserialize_reader<R: io::Read>(reader: &mut R) -> io::Result<Self> {
+        let first = u8::deserialize_reader(reader)?;
+        if first != Self::VERSION_MARKER {
+            // Unversioned: `first` is the low byte of the key count
+            let mut count = [first, 0, 0, 0];
+            reader.read_exact(&mut count[1..])?;
+            let count = u32::from_le_bytes(count);
+            let entries = (0..count)
+                .map(|_| EncryptionKeyConfig::deserialize_reader(reader).map(KeystoreEntry::new))
+                .collect::<io::Result<_>>()?;
+            return Ok(Self(entries));
+        }
+        match u8::deserialize_reader(reader)? {
+            Self::VERSION => Ok(Self(Vec::deserialize_reader(reader)?)),
+            version => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Unsupported keystore version {}", version),
+            )),
+        }
+    }
+}
+
+impl BorshSerialize for KeystoreEntry {
+    fn serialize<W: io::Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.key.try_to_vec()?.serialize(writer)?;
+        self.config.try_to_vec()?.serialize(writer)
+    }
+}
+
+impl BorshDeserialize for KeystoreEntry {
+    fn deserialize_reader<R: io::Read>(reader: &mut R) -> io::Result<Self> {
+        let key = Vec::<u8>::deserialize_reader(reader)?;
+        let config = Vec::<u8>::deserialize_reader(reader)?;
+        Ok(Self {
+            key: EncryptionKeyConfig::try_from_slice(&key)?,
+            config: EntryConfig::try_from_slice(&config)?,
+        })
+    }
+}
+
+impl fmt::Debug for KeystoreEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeystoreEntry")
+            .field("fingerprint", &self.key.fingerprint())
+            .field("key", &self.key)
+            .field("config", &self.config)
+            .finish()
+    }
+}
+
+impl fmt::Display for KeystoreEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({:?}", self.key, self.config.status)?;
+        if self.config.primary {
+            write!(f, ", primary")?;
+        }
+        if let Some(label) = &self.config.metadata.label {
+            write!(f, ", {:?}", label)?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// Canonical identifier of a key, see `EncryptionKeyConfig::fingerprint`
+///
+/// Two entries holding the same key share a fingerprint regardless of their
+/// configuration. Envelopes use it to label each recipient's slot.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, BorshDeserialize, BorshSerialize)]
+pub struct Fingerprint(pub [u8; 32]);
+
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for byte in self.0 {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Fingerprint({})", self)
+    }
+}
+
+/// Per-entry configuration
 #[derive(Clone, Debug, Default, PartialEq, BorshDeserialize, BorshSerialize)]
-pub struct Keystore(pub Vec<EncryptionKeyConfig>);
+pub struct EntryConfig {
+    /// Lifecycle status of the key
+    pub status: KeyStatus,
+    /// Unix timestamp from which senders must stop using the key
+    pub expires_at: Option<UnixTimestamp>,
+    /// Whether this is the owner's preferred key for its algorithm
+    ///
+    /// At most one entry per algorithm is primary; see
+    /// `Keystore::set_primary`.
+    pub primary: bool,
+    /// What the owner allows the key to be used for
+    pub usage: KeyUsage,
+    /// Descriptive metadata for displaying the entry
+    pub metadata: EntryMetadata,
+}
+
+/// Descriptive metadata for a keystore entry
+///
+/// None of these fields affect how senders use the key; they let wallets
+/// tell the owner's keys apart.
+#[derive(Clone, Debug, Default, PartialEq, BorshDeserialize, BorshSerialize)]
+pub struct EntryMetadata {
+    /// Human-readable label, e.g. "Laptop key"
+    pub label: Option<String>,
+    /// Name of the device holding the secret key
+    pub device: Option<String>,
+    /// Slot at which the entry was added
+    pub created_at_slot: Option<Slot>,
+    /// Origin of the application that added the entry, e.g.
+    /// `https://wallet.example`
+    pub origin: Option<String>,
+}
+
+impl EntryMetadata {
+    /// Maximum length in bytes of each text field
+    pub const MAX_TEXT_LEN: usize = 64;
+
+    /// Check that every text field fits within `MAX_TEXT_LEN`
+    pub fn validate(&self) -> Result<(), KeyringError> {
+        for text in [&self.label, &self.device, &self.origin]
+            .into_iter()
+            .flatten()
+        {
+            if text.len() > Self::MAX_TEXT_LEN {
+                return Err(KeyringError::MetadataTooLong);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Set of uses the owner allows for a key
+///
+/// An empty set means the owner placed no restriction on the key.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, BorshDeserialize, BorshSerialize)]
+pub struct KeyUsage(pub u8);
+
+impl KeyUsage {
+    /// Encrypting messages to the key
+    pub const ENCRYPTION: Self = Self(1 << 0);
+    /// Deriving shared secrets with the key
+    pub const KEY_AGREEMENT: Self = Self(1 << 1);
+    /// Verifying signatures made by the key
+    pub const SIGNATURE_VERIFICATION: Self = Self(1 << 2);
+
+    /// Whether the key may be used for every use in `usage`
+    pub fn permits(self, usage: KeyUsage) -> bool {
+        self.0 == 0 || self.0 & usage.0 == usage.0
+    }
+}
+
+impl std::ops::BitOr for KeyUsage {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+/// Lifecycle status of a keystore entry
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, BorshDeserialize, BorshSerialize)]
+pub enum KeyStatus {
+    /// The key may be used by senders
+    #[default]
+    Active,
+    /// The key was withdrawn and must not be used by senders
+    Revoked,
+    /// The key was rotated out: senders must not use it, but the owner keeps
+    /// it to decrypt earlier messages
+    Retired,
+}
+
+/// Encryption algorithms recognized in a keystore, without key material
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum KeyAlgorithm {
+    /// Curve25519 encryption algorithm
+    Curve25519,
+    /// RSA encryption algorithm
+    Rsa,
+    /// ComplexAlgorithm encryption algorithm (example)
+    ComplexAlgorithm,
+    /// Token-2022 confidential transfer ElGamal public key
+    ElGamal,
+    /// Token-2022 confidential transfer AE key commitment
+    AeKey,
+    /// ML-KEM post-quantum key encapsulation algorithm
+    MlKem,
+    /// secp256k1 public key
+    Secp256k1,
+    /// NIST P-256 public key
+    P256,
+    /// Curve25519 key derived from the wallet's Ed25519 keypair
+    WalletDerivedCurve25519,
+    /// HPKE recipient configuration
+    Hpke,
+}
+
+impl KeyAlgorithm {
+    /// Whether a sender supporting `other` can encrypt to a key of this
+    /// algorithm
+    ///
+    /// A wallet-derived Curve25519 key is an X25519 key like any published
+    /// one, so the two Curve25519 algorithms are compatible; every other
+    /// algorithm is only compatible with itself.
+    pub fn is_compatible_with(self, other: KeyAlgorithm) -> bool {
+        self.key_type() == other.key_type()
+    }
+
+    fn key_type(self) -> Self {
+        match self {
+            KeyAlgorithm::WalletDerivedCurve25519 => KeyAlgorithm::Curve25519,
+            algorithm => algorithm,
+        }
+    }
+}
 
 /// An enum for defining recognized encryption algorithms
 #[derive(Clone, Debug, PartialEq, BorshDeserialize, BorshSerialize)]
@@ -15,6 +606,76 @@ pub enum EncryptionKeyConfig {
     Rsa(Rsa),
     /// ComplexAlgorithm encryption algorithm (example)
     ComplexAlgorithm(ComplexAlgorithm),
+    /// Token-2022 confidential transfer ElGamal public key
+    ElGamal(ElGamal),
+    /// Token-2022 confidential transfer authenticated encryption key
+    /// commitment
+    AeKey(AeKey),
+    /// ML-KEM (FIPS 203) post-quantum key encapsulation algorithm
+    MlKem(MlKem),
+    /// secp256k1 public key
+    Secp256k1(Secp256k1),
+    /// NIST P-256 (secp256r1) public key, as used by WebAuthn passkeys
+    P256(P256),
+    /// Curve25519 encryption key derived from the wallet's own Ed25519
+    /// keypair
+    ///
+    /// No key bytes are stored: senders convert the keyring authority's
+    /// address into the X25519 public key with `x25519::public_key_from_wallet`.
+    WalletDerivedCurve25519,
+    /// HPKE (RFC 9180) recipient configuration
+    Hpke(Hpke),
+}
+
+impl EncryptionKeyConfig {
+    /// The algorithm of this key
+    pub fn algorithm(&self) -> KeyAlgorithm {
+        match self {
+            EncryptionKeyConfig::Curve25519(_) => KeyAlgorithm::Curve25519,
+            EncryptionKeyConfig::Rsa(_) => KeyAlgorithm::Rsa,
+            EncryptionKeyConfig::ComplexAlgorithm(_) => KeyAlgorithm::ComplexAlgorithm,
+            EncryptionKeyConfig::ElGamal(_) => KeyAlgorithm::ElGamal,
+            EncryptionKeyConfig::AeKey(_) => KeyAlgorithm::AeKey,
+            EncryptionKeyConfig::MlKem(_) => KeyAlgorithm::MlKem,
+            EncryptionKeyConfig::Secp256k1(_) => KeyAlgorithm::Secp256k1,
+            EncryptionKeyConfig::P256(_) => KeyAlgorithm::P256,
+            EncryptionKeyConfig::WalletDerivedCurve25519 => KeyAlgorithm::WalletDerivedCurve25519,
+            EncryptionKeyConfig::Hpke(_) => KeyAlgorithm::Hpke,
+        }
+    }
+
+    /// Domain separator for key fingerprints
+    const FINGERPRINT_PREFIX: &'static [u8] = b"spl-keyring:key-fingerprint";
+
+    /// The canonical fingerprint of this key
+    ///
+    /// Hashes the key's full Borsh encoding: the algorithm discriminator,
+    /// then every field of the key, including parameters such as an HPKE
+    /// cipher suite or an ElGamal key's mint. Entry configuration is not
+    /// part of the key, so it doesn't affect the fingerprint.
+    pub fn fingerprint(&self) -> Fingerprint {
+        let bytes = self
+            .try_to_vec()
+            .expect("serializing a key into a Vec can't fail");
+        Fingerprint(hashv(&[Self::FINGERPRINT_PREFIX, &bytes]).to_bytes())
+    }
+
+    /// Check that the key is well-formed for its algorithm
+    pub fn validate(&self) -> Result<(), KeyringError> {
+        match self {
+            EncryptionKeyConfig::MlKem(key) => key.validate(),
+            EncryptionKeyConfig::Secp256k1(key) => key.validate(),
+            EncryptionKeyConfig::P256(key) => key.validate(),
+            EncryptionKeyConfig::Hpke(key) => key.validate(),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl fmt::Display for EncryptionKeyConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} {}", self.algorithm(), self.fingerprint())
+    }
 }
 
 /// Curve25519 encryption algorithm
@@ -35,3 +696,840 @@ pub struct ComplexAlgorithm {
     /// The associated data used for encryption
     pub aad: [u8; 12],
 }
+
+/// Token-2022 confidential transfer ElGamal public key
+#[derive(Clone, Debug, PartialEq, BorshDeserialize, BorshSerialize)]
+pub struct ElGamal {
+    /// The ElGamal public key, laid out exactly like the zk-token-sdk
+    /// `pod::ElGamalPubkey`
+    pub pubkey: [u8; 32],
+    /// The mint this key is restricted to, if any
+    pub mint: Option<Pubkey>,
+}
+
+/// Commitment to a Token-2022 confidential transfer authenticated encryption
+/// (AE) key
+///
+/// The AE key itself is secret, so only a hash of it is published. This lets
+/// the owner check which AE key a keystore entry refers to.
+#[derive(Clone, Debug, PartialEq, BorshDeserialize, BorshSerialize)]
+pub struct AeKey {
+    /// Hash commitment to the AE key
+    pub commitment: [u8; 32],
+    /// The mint this key is restricted to, if any
+    pub mint: Option<Pubkey>,
+}
+
+impl AeKey {
+    /// Domain separator for AE key commitments
+    const COMMITMENT_PREFIX: &'static [u8] = b"spl-keyring:ae-key";
+
+    /// Create a new AE key commitment from the raw AE key bytes
+    pub fn new(ae_key: &[u8], mint: Option<Pubkey>) -> Self {
+        Self {
+            commitment: Self::commit(ae_key),
+            mint,
+        }
+    }
+
+    /// Check whether the provided AE key matches this commitment
+    pub fn matches(&self, ae_key: &[u8]) -> bool {
+        self.commitment == Self::commit(ae_key)
+    }
+
+    fn commit(ae_key: &[u8]) -> [u8; 32] {
+        hashv(&[Self::COMMITMENT_PREFIX, ae_key]).to_bytes()
+    }
+}
+
+/// ML-KEM parameter sets
+#[derive(Clone, Copy, Debug, PartialEq, Eq, BorshDeserialize, BorshSerialize)]
+pub enum MlKemParameterSet {
+    /// ML-KEM-512
+    MlKem512,
+    /// ML-KEM-768
+    MlKem768,
+    /// ML-KEM-1024
+    MlKem1024,
+}
+
+impl MlKemParameterSet {
+    /// Length in bytes of an encapsulation key for this parameter set
+    pub const fn encapsulation_key_len(&self) -> usize {
+        match self {
+            MlKemParameterSet::MlKem512 => 800,
+            MlKemParameterSet::MlKem768 => 1184,
+            MlKemParameterSet::MlKem1024 => 1568,
+        }
+    }
+}
+
+/// ML-KEM (FIPS 203) post-quantum key encapsulation algorithm
+#[derive(Clone, Debug, PartialEq, BorshDeserialize, BorshSerialize)]
+pub struct MlKem {
+    /// The parameter set the key was generated for
+    pub parameter_set: MlKemParameterSet,
+    /// The encapsulation (public) key
+    pub key: Vec<u8>,
+}
+
+impl MlKem {
+    /// Create a new ML-KEM entry, checking the key length against the
+    /// parameter set
+    pub fn new(parameter_set: MlKemParameterSet, key: Vec<u8>) -> Result<Self, KeyringError> {
+        let entry = Self { parameter_set, key };
+        entry.validate()?;
+        Ok(entry)
+    }
+
+    /// Check the key length against the parameter set
+    pub fn validate(&self) -> Result<(), KeyringError> {
+        let expected = self.parameter_set.encapsulation_key_len();
+        if self.key.len() != expected {
+            return Err(KeyringError::InvalidKeyLength {
+                expected,
+                actual: self.key.len(),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// A SEC1-encoded elliptic curve point
+#[derive(Clone, Debug, PartialEq, BorshDeserialize, BorshSerialize)]
+pub enum Sec1Point {
+    /// Compressed point (`0x02`/`0x03` prefix followed by the x-coordinate)
+    Compressed([u8; 33]),
+    /// Uncompressed point (`0x04` prefix followed by both coordinates)
+    Uncompressed([u8; 65]),
+}
+
+impl Sec1Point {
+    /// The SEC1-encoded point bytes
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            Sec1Point::Compressed(bytes) => bytes,
+            Sec1Point::Uncompressed(bytes) => bytes,
+        }
+    }
+}
+
+/// secp256k1 public key
+#[derive(Clone, Debug, PartialEq, BorshDeserialize, BorshSerialize)]
+pub struct Secp256k1(pub Sec1Point);
+
+impl Secp256k1 {
+    /// Check that the key is a valid point on the secp256k1 curve
+    pub fn validate(&self) -> Result<(), KeyringError> {
+        self.to_public_key().map(|_| ())
+    }
+
+    /// Derive the Ethereum address for this key
+    ///
+    /// The address is the last 20 bytes of the Keccak-256 hash of the
+    /// uncompressed point, without its `0x04` prefix.
+    pub fn ethereum_address(&self) -> Result<[u8; 20], KeyringError> {
+        use k256::elliptic_curve::sec1::ToEncodedPoint;
+
+        let point = self.to_public_key()?.to_encoded_point(false);
+        let hash = keccak::hash(&point.as_bytes()[1..]).to_bytes();
+        let mut address = [0u8; 20];
+        address.copy_from_slice(&hash[12..]);
+        Ok(address)
+    }
+
+    fn to_public_key(&self) -> Result<k256::PublicKey, KeyringError> {
+        k256::PublicKey::from_sec1_bytes(self.0.as_bytes()).map_err(|_| KeyringError::InvalidKey)
+    }
+}
+
+/// NIST P-256 (secp256r1) public key, as used by WebAuthn passkeys
+#[derive(Clone, Debug, PartialEq, BorshDeserialize, BorshSerialize)]
+pub struct P256(pub Sec1Point);
+
+impl P256 {
+    /// Check that the key is a valid point on the P-256 curve
+    pub fn validate(&self) -> Result<(), KeyringError> {
+        p256::PublicKey::from_sec1_bytes(self.0.as_bytes())
+            .map(|_| ())
+            .map_err(|_| KeyringError::InvalidKey)
+    }
+}
+
+/// HPKE cipher suite, as IANA-registered identifiers
+#[derive(Clone, Copy, Debug, PartialEq, Eq, BorshDeserialize, BorshSerialize)]
+pub struct HpkeConfig {
+    /// Key encapsulation mechanism identifier
+    pub kem_id: u16,
+    /// Key derivation function identifier
+    pub kdf_id: u16,
+    /// AEAD identifier
+    pub aead_id: u16,
+}
+
+impl HpkeConfig {
+    /// Whether this client can seal and open messages with this cipher suite
+    pub fn is_supported(&self) -> bool {
+        self.kem_id == hpke::KEM_X25519_HKDF_SHA256
+            && self.kdf_id == hpke::KDF_HKDF_SHA256
+            && self.aead_id == hpke::AEAD_CHACHA20_POLY1305
+    }
+}
+
+/// HPKE (RFC 9180) recipient configuration
+#[derive(Clone, Debug, PartialEq, BorshDeserialize, BorshSerialize)]
+pub struct Hpke {
+    /// The serialized KEM public key
+    pub public_key: Vec<u8>,
+    /// The cipher suite the recipient accepts
+    pub config: HpkeConfig,
+}
+
+impl Hpke {
+    /// Check the public key length for KEMs this client recognizes
+    pub fn validate(&self) -> Result<(), KeyringError> {
+        if self.config.kem_id == hpke::KEM_X25519_HKDF_SHA256 && self.public_key.len() != 32 {
+            return Err(KeyringError::InvalidKeyLength {
+                expected: 32,
+                actual: self.public_key.len(),
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // secp256k1 generator point, i.e. the public key for secret key `1`
+    const SECP256K1_G_COMPRESSED: &str =
+        "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+    const SECP256K1_G_UNCOMPRESSED: &str = "0479be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798483ada7726a3c4655da4fbfc0e1108a8fd17b448a68554199c47d08ffb10d4b8";
+    // P-256 generator point
+    const P256_G_COMPRESSED: &str =
+        "036b17d1f2e12c4247f8bce6e563a440f277037d812deb33a0f4a13945d898c296";
+
+    fn decode<const N: usize>(hex: &str) -> [u8; N] {
+        let mut bytes = [0u8; N];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).unwrap();
+        }
+        bytes
+    }
+
+    fn curve25519_entry(byte: u8, config: EntryConfig) -> KeystoreEntry {
+        KeystoreEntry {
+            key: EncryptionKeyConfig::Curve25519(Curve25519([byte; 32])),
+            config,
+        }
+    }
+
+    #[test]
+    fn test_negotiate() {
+        let rsa = KeystoreEntry::new(EncryptionKeyConfig::Rsa(Rsa([1; 64])));
+        let older = curve25519_entry(2, EntryConfig::default());
+        let newer = curve25519_entry(3, EntryConfig::default());
+        let keystore = Keystore(vec![rsa.clone(), older.clone(), newer.clone()]);
+
+        // Sender preference decides the algorithm, the newest entry wins
+        assert_eq!(
+            keystore
+                .negotiate(&[KeyAlgorithm::Curve25519, KeyAlgorithm::Rsa], 0)
+                .unwrap(),
+            &newer
+        );
+        assert_eq!(
+            keystore
+                .negotiate(&[KeyAlgorithm::MlKem, KeyAlgorithm::Rsa], 0)
+                .unwrap(),
+            &rsa
+        );
+        assert!(matches!(
+            keystore.negotiate(&[KeyAlgorithm::MlKem], 0),
+            Err(KeyringError::NoCompatibleKey)
+        ));
+    }
+
+    #[test]
+    fn test_negotiate_wallet_derived() {
+        let wallet_derived = KeystoreEntry::new(EncryptionKeyConfig::WalletDerivedCurve25519);
+        let keystore = Keystore(vec![wallet_derived.clone()]);
+
+        // A wallet-derived key serves senders asking for Curve25519
+        assert_eq!(
+            keystore.negotiate(&[KeyAlgorithm::Curve25519], 0).unwrap(),
+            &wallet_derived
+        );
+
+        // An exact match wins over a compatible algorithm, whatever the order
+        let published = curve25519_entry(1, EntryConfig::default());
+        let keystore = Keystore(vec![published.clone(), wallet_derived.clone()]);
+        assert_eq!(
+            keystore.negotiate(&[KeyAlgorithm::Curve25519], 0).unwrap(),
+            &published
+        );
+        assert_eq!(
+            keystore
+                .negotiate(&[KeyAlgorithm::WalletDerivedCurve25519], 0)
+                .unwrap(),
+            &wallet_derived
+        );
+        assert!(matches!(
+            keystore.negotiate(&[KeyAlgorithm::Rsa], 0),
+            Err(KeyringError::NoCompatibleKey)
+        ));
+    }
+
+    #[test]
+    fn test_negotiate_primary() {
+        let primary = curve25519_entry(
+            1,
+            EntryConfig {
+                primary: true,
+                ..EntryConfig::default()
+            },
+        );
+        let newer = curve25519_entry(2, EntryConfig::default());
+        let keystore = Keystore(vec![primary.clone(), newer]);
+
+        assert_eq!(
+            keystore.negotiate(&[KeyAlgorithm::Curve25519], 0).unwrap(),
+            &primary
+        );
+    }
+
+    #[test]
+    fn test_negotiate_usage() {
+        let signing = curve25519_entry(
+            1,
+            EntryConfig {
+                primary: true,
+                usage: KeyUsage::SIGNATURE_VERIFICATION,
+                ..EntryConfig::default()
+            },
+        );
+        let encryption = curve25519_entry(
+            2,
+            EntryConfig {
+                usage: KeyUsage::ENCRYPTION | KeyUsage::KEY_AGREEMENT,
+                ..EntryConfig::default()
+            },
+        );
+        let keystore = Keystore(vec![signing, encryption.clone()]);
+
+        assert_eq!(
+            keystore.negotiate(&[KeyAlgorithm::Curve25519], 0).unwrap(),
+            &encryption
+        );
+    }
+
+    #[test]
+    fn test_metadata_round_trip() {
+        let entry = KeystoreEntry {
+            key: EncryptionKeyConfig::Curve25519(Curve25519([1; 32])),
+            config: EntryConfig {
+                metadata: EntryMetadata {
+                    label: Some("Laptop key".to_string()),
+                    device: Some("MacBook".to_string()),
+                    created_at_slot: Some(42),
+                    origin: Some("https://wallet.example".to_string()),
+                },
+                ..EntryConfig::default()
+            },
+        };
+        let keystore = Keystore(vec![entry]);
+        let data = keystore.try_to_vec().unwrap();
+        assert_eq!(Keystore::try_from_slice(&data).unwrap(), keystore);
+    }
+
+    #[test]
+    fn test_metadata_too_long() {
+        let metadata = EntryMetadata {
+            label: Some("x".repeat(EntryMetadata::MAX_TEXT_LEN + 1)),
+            ..EntryMetadata::default()
+        };
+        assert!(matches!(
+            metadata.validate(),
+            Err(KeyringError::MetadataTooLong)
+        ));
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let entry = curve25519_entry(1, EntryConfig::default());
+        let fingerprint = entry.key.fingerprint();
+
+        // Configuration doesn't change the fingerprint
+        let primary = curve25519_entry(
+            1,
+            EntryConfig {
+                primary: true,
+                ..EntryConfig::default()
+            },
+        );
+        assert_eq!(primary.key.fingerprint(), fingerprint);
+        assert_ne!(
+            curve25519_entry(2, EntryConfig::default())
+                .key
+                .fingerprint(),
+            fingerprint
+        );
+        assert_ne!(
+            EncryptionKeyConfig::Rsa(Rsa([1; 64])).fingerprint(),
+            EncryptionKeyConfig::Rsa(Rsa([0; 64])).fingerprint(),
+        );
+
+        // The same key bytes under another algorithm have another fingerprint
+        assert_ne!(
+            EncryptionKeyConfig::ElGamal(ElGamal {
+                pubkey: [1; 32],
+                mint: None,
+            })
+            .fingerprint(),
+            fingerprint
+        );
+
+        let hex = fingerprint.to_string();
+        assert_eq!(hex.len(), 64);
+        assert!(entry.to_string().contains(&hex));
+        assert!(format!("{:?}", entry).contains(&hex));
+    }
+
+    #[test]
+    fn test_revoke_and_remove_by_fingerprint() {
+        let first = curve25519_entry(
+            1,
+            EntryConfig {
+                primary: true,
+                ..EntryConfig::default()
+            },
+        );
+        let second = curve25519_entry(2, EntryConfig::default());
+        let mut keystore = Keystore(vec![first.clone(), second.clone()]);
+
+        assert_eq!(
+            keystore.find_by_fingerprint(&second.key.fingerprint()),
+            Some(&second)
+        );
+
+        keystore.revoke(&first.key.fingerprint()).unwrap();
+        assert_eq!(keystore.0[0].config.status, KeyStatus::Revoked);
+        assert!(!keystore.0[0].config.primary);
+
+        assert_eq!(
+            keystore
+                .remove_by_fingerprint(&second.key.fingerprint())
+                .unwrap(),
+            second
+        );
+        assert_eq!(keystore.0.len(), 1);
+        assert!(matches!(
+            keystore.remove_by_fingerprint(&second.key.fingerprint()),
+            Err(KeyringError::EntryNotFound)
+        ));
+    }
+
+    #[test]
+    fn test_remove() {
+        let first = curve25519_entry(1, EntryConfig::default());
+        let second = curve25519_entry(2, EntryConfig::default());
+        let mut keystore = Keystore(vec![first.clone(), second.clone()]);
+
+        assert_eq!(keystore.remove(&first.key).unwrap(), first);
+        assert_eq!(keystore, Keystore(vec![second]));
+        assert!(matches!(
+            keystore.remove(&first.key),
+            Err(KeyringError::EntryNotFound)
+        ));
+    }
+
+    #[test]
+    fn test_entries_by_algorithm() {
+        let first = curve25519_entry(1, EntryConfig::default());
+        let rsa = KeystoreEntry::new(EncryptionKeyConfig::Rsa(Rsa([5; 64])));
+        let second = curve25519_entry(2, EntryConfig::default());
+        let keystore = Keystore(vec![first.clone(), rsa.clone(), second.clone()]);
+
+        assert_eq!(
+            keystore
+                .entries_by_algorithm(KeyAlgorithm::Curve25519)
+                .collect::<Vec<_>>(),
+            vec![&first, &second]
+        );
+        assert_eq!(
+            keystore
+                .entries_by_algorithm(KeyAlgorithm::Rsa)
+                .collect::<Vec<_>>(),
+            vec![&rsa]
+        );
+        assert_eq!(
+            keystore.entries_by_algorithm(KeyAlgorithm::MlKem).count(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_add_is_idempotent() {
+        let entry = curve25519_entry(1, EntryConfig::default());
+        let mut keystore = Keystore::default();
+        assert!(keystore.add(entry.clone()));
+
+        // Same key with a different configuration
+        let relabeled = curve25519_entry(
+            1,
+            EntryConfig {
+                primary: true,
+                ..EntryConfig::default()
+            },
+        );
+        assert!(!keystore.add(relabeled));
+        assert_eq!(keystore, Keystore(vec![entry.clone()]));
+
+        assert!(matches!(
+            keystore.rotate(&entry.key, entry.clone()),
+            Err(KeyringError::DuplicateEntry)
+        ));
+    }
+
+    #[test]
+    fn test_add_primary() {
+        let first = curve25519_entry(
+            1,
+            EntryConfig {
+                primary: true,
+                ..EntryConfig::default()
+            },
+        );
+        let rsa = KeystoreEntry {
+            key: EncryptionKeyConfig::Rsa(Rsa([2; 64])),
+            config: EntryConfig {
+                primary: true,
+                ..EntryConfig::default()
+            },
+        };
+        let mut keystore = Keystore(vec![first, rsa]);
+
+        // A new primary entry takes the flag from its algorithm's old one
+        let second = curve25519_entry(
+            3,
+            EntryConfig {
+                primary: true,
+                ..EntryConfig::default()
+            },
+        );
+        assert!(keystore.add(second));
+        let primaries: Vec<_> = keystore.0.iter().map(|e| e.config.primary).collect();
+        assert_eq!(primaries, [false, true, true]);
+
+        // A non-primary entry leaves the flags alone
+        assert!(keystore.add(curve25519_entry(4, EntryConfig::default())));
+        let primaries: Vec<_> = keystore.0.iter().map(|e| e.config.primary).collect();
+        assert_eq!(primaries, [false, true, true, false]);
+    }
+
+    #[test]
+    fn test_entry_framing() {
+        let entry = curve25519_entry(7, EntryConfig::default());
+        let data = Keystore(vec![entry.clone()]).try_to_vec().unwrap();
+        let key = entry.key.try_to_vec().unwrap();
+
+        // Version, entry count, then the length-prefixed key
+        assert_eq!(&data[..2], &[Keystore::VERSION_MARKER, Keystore::VERSION]);
+        assert_eq!(&data[2..6], &1u32.to_le_bytes());
+        assert_eq!(&data[6..10], &(key.len() as u32).to_le_bytes());
+        assert_eq!(&data[10..10 + key.len()], &key[..]);
+        assert_eq!(
+            Keystore::try_from_slice(&data).unwrap(),
+            Keystore(vec![entry])
+        );
+
+        // An unknown algorithm fails the whole keystore
+        let mut unknown = data;
+        unknown[10] = u8::MAX;
+        assert!(Keystore::try_from_slice(&unknown).is_err());
+    }
+
+    #[test]
+    fn test_unversioned_keystore() {
+        // Keystores used to be a bare list of keys
+        let keys = vec![
+            EncryptionKeyConfig::Curve25519(Curve25519([1; 32])),
+            EncryptionKeyConfig::Rsa(Rsa([2; 64])),
+            EncryptionKeyConfig::ComplexAlgorithm(ComplexAlgorithm {
+                key: [3; 32],
+                nonce: [4; 12],
+                aad: [5; 12],
+            }),
+        ];
+        let data = keys.try_to_vec().unwrap();
+        assert_eq!(data[0], 3);
+
+        let keystore = Keystore::try_from_slice(&data).unwrap();
+        assert_eq!(
+            keystore,
+            Keystore(keys.into_iter().map(KeystoreEntry::new).collect())
+        );
+
+        // Rewriting it produces the current layout
+        let data = keystore.try_to_vec().unwrap();
+        assert_eq!(&data[..2], &[Keystore::VERSION_MARKER, Keystore::VERSION]);
+        assert_eq!(Keystore::try_from_slice(&data).unwrap(), keystore);
+
+        assert_eq!(
+            Keystore::try_from_slice(&Vec::<EncryptionKeyConfig>::new().try_to_vec().unwrap())
+                .unwrap(),
+            Keystore::default()
+        );
+    }
+
+    #[test]
+    fn test_unknown_keystore_version() {
+        let mut data = Keystore::default().try_to_vec().unwrap();
+        data[1] = Keystore::VERSION + 1;
+        assert!(Keystore::try_from_slice(&data).is_err());
+    }
+
+    #[test]
+    fn test_rotate() {
+        let old = curve25519_entry(
+            1,
+            EntryConfig {
+                primary: true,
+                ..EntryConfig::default()
+            },
+        );
+        let new = curve25519_entry(2, EntryConfig::default());
+        let mut keystore = Keystore(vec![old.clone()]);
+
+        keystore.rotate(&old.key, new.clone()).unwrap();
+        assert_eq!(keystore.0[0].config.status, KeyStatus::Retired);
+        assert!(!keystore.0[0].config.primary);
+        assert!(keystore.0[1].config.primary);
+
+        // Only the new key is offered to senders
+        assert_eq!(
+            keystore
+                .negotiate(&[KeyAlgorithm::Curve25519], 0)
+                .unwrap()
+                .key,
+            new.key
+        );
+
+        // A missing key can't be rotated
+        assert!(matches!(
+            keystore.rotate(
+                &EncryptionKeyConfig::Rsa(Rsa([3; 64])),
+                curve25519_entry(4, EntryConfig::default())
+            ),
+            Err(KeyringError::EntryNotFound)
+        ));
+
+        // Nor can one that was already rotated out
+        assert!(matches!(
+            keystore.rotate(&old.key, curve25519_entry(5, EntryConfig::default())),
+            Err(KeyringError::InactiveEntry)
+        ));
+        assert_eq!(keystore.0.len(), 2);
+    }
+
+    #[test]
+    fn test_rotate_to_primary() {
+        let primary = curve25519_entry(
+            1,
+            EntryConfig {
+                primary: true,
+                ..EntryConfig::default()
+            },
+        );
+        let old = curve25519_entry(2, EntryConfig::default());
+        let mut keystore = Keystore(vec![primary, old.clone()]);
+
+        // Rotating a non-primary key to a primary one moves the flag
+        let new = curve25519_entry(
+            3,
+            EntryConfig {
+                primary: true,
+                ..EntryConfig::default()
+            },
+        );
+        keystore.rotate(&old.key, new).unwrap();
+        let primaries: Vec<_> = keystore.0.iter().map(|e| e.config.primary).collect();
+        assert_eq!(primaries, [false, false, true]);
+    }
+
+    #[test]
+    fn test_set_primary() {
+        let first = curve25519_entry(1, EntryConfig::default());
+        let second = curve25519_entry(2, EntryConfig::default());
+        let rsa = KeystoreEntry {
+            key: EncryptionKeyConfig::Rsa(Rsa([3; 64])),
+            config: EntryConfig {
+                primary: true,
+                ..EntryConfig::default()
+            },
+        };
+        let mut keystore = Keystore(vec![first.clone(), second.clone(), rsa.clone()]);
+
+        keystore.set_primary(&first.key).unwrap();
+        keystore.set_primary(&second.key).unwrap();
+        let primaries: Vec<_> = keystore.0.iter().map(|e| e.config.primary).collect();
+        assert_eq!(primaries, [false, true, true]);
+
+        assert!(matches!(
+            keystore.set_primary(&EncryptionKeyConfig::Curve25519(Curve25519([4; 32]))),
+            Err(KeyringError::EntryNotFound)
+        ));
+    }
+
+    #[test]
+    fn test_negotiate_skips_unusable() {
+        let usable = curve25519_entry(1, EntryConfig::default());
+        let revoked = curve25519_entry(
+            2,
+            EntryConfig {
+                status: KeyStatus::Revoked,
+                primary: true,
+                ..EntryConfig::default()
+            },
+        );
+        let expired = curve25519_entry(
+            3,
+            EntryConfig {
+                expires_at: Some(100),
+                ..EntryConfig::default()
+            },
+        );
+        let keystore = Keystore(vec![usable.clone(), revoked, expired.clone()]);
+
+        assert_eq!(
+            keystore.negotiate(&[KeyAlgorithm::Curve25519], 99).unwrap(),
+            &expired
+        );
+        assert_eq!(
+            keystore
+                .negotiate(&[KeyAlgorithm::Curve25519], 100)
+                .unwrap(),
+            &usable
+        );
+    }
+
+    #[test]
+    fn test_selectors_prefer_usable_entries() {
+        let mint = Pubkey::new_unique();
+        let elgamal = |byte, config| KeystoreEntry {
+            key: EncryptionKeyConfig::ElGamal(ElGamal {
+                pubkey: [byte; 32],
+                mint: None,
+            }),
+            config,
+        };
+        let hpke = |byte, config| KeystoreEntry {
+            key: EncryptionKeyConfig::Hpke(Hpke {
+                public_key: vec![byte; 32],
+                config: HpkeConfig {
+                    kem_id: hpke::KEM_X25519_HKDF_SHA256,
+                    kdf_id: hpke::KDF_HKDF_SHA256,
+                    aead_id: hpke::AEAD_CHACHA20_POLY1305,
+                },
+            }),
+            config,
+        };
+        let primary = EntryConfig {
+            primary: true,
+            ..EntryConfig::default()
+        };
+        let expired = EntryConfig {
+            expires_at: Some(100),
+            ..EntryConfig::default()
+        };
+        let signing_only = EntryConfig {
+            usage: KeyUsage::SIGNATURE_VERIFICATION,
+            ..EntryConfig::default()
+        };
+        let keystore = Keystore(vec![
+            curve25519_entry(1, primary.clone()),
+            curve25519_entry(2, EntryConfig::default()),
+            curve25519_entry(3, expired.clone()),
+            elgamal(4, primary.clone()),
+            elgamal(5, signing_only.clone()),
+            hpke(6, EntryConfig::default()),
+            hpke(7, expired.clone()),
+            hpke(8, signing_only),
+        ]);
+        let wallet = Pubkey::new_unique();
+
+        // Primary entries win over newer ones, before and after expiry
+        assert_eq!(
+            keystore.curve25519_key(&wallet, 0),
+            Some(x25519_dalek::PublicKey::from([1; 32]))
+        );
+        let mut keystore = keystore;
+        keystore.0[0].config = expired.clone();
+        assert_eq!(
+            keystore.curve25519_key(&wallet, 0),
+            Some(x25519_dalek::PublicKey::from([3; 32]))
+        );
+        assert_eq!(
+            keystore.curve25519_key(&wallet, 100),
+            Some(x25519_dalek::PublicKey::from([2; 32]))
+        );
+
+        // Usage-restricted entries are skipped, even when newer
+        assert_eq!(keystore.elgamal_pubkey(&mint, 0).unwrap().pubkey, [4; 32]);
+        keystore.0[3].config.status = KeyStatus::Retired;
+        assert_eq!(keystore.elgamal_pubkey(&mint, 0), None);
+
+        // Expired HPKE configurations fall back to older ones
+        assert_eq!(keystore.hpke(0).unwrap().public_key, vec![7; 32]);
+        assert_eq!(keystore.hpke(100).unwrap().public_key, vec![6; 32]);
+        assert_eq!(
+            keystore.envelope_key(&wallet, 100),
+            Some(RecipientKey::Hpke(keystore.hpke(100).unwrap().clone()))
+        );
+        keystore.0.truncate(5);
+        assert_eq!(
+            keystore.envelope_key(&wallet, 100),
+            Some(RecipientKey::Curve25519(x25519_dalek::PublicKey::from(
+                [2; 32]
+            )))
+        );
+    }
+
+    #[test]
+    fn test_secp256k1_ethereum_address() {
+        let expected: [u8; 20] = decode("7e5f4552091a69125d5dfcb7b8c2659029395bdf");
+
+        let compressed = Secp256k1(Sec1Point::Compressed(decode(SECP256K1_G_COMPRESSED)));
+        assert_eq!(compressed.ethereum_address().unwrap(), expected);
+
+        let uncompressed = Secp256k1(Sec1Point::Uncompressed(decode(SECP256K1_G_UNCOMPRESSED)));
+        assert_eq!(uncompressed.ethereum_address().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_validate_points() {
+        let secp256k1 = EncryptionKeyConfig::Secp256k1(Secp256k1(Sec1Point::Compressed(decode(
+            SECP256K1_G_COMPRESSED,
+        ))));
+        assert!(secp256k1.validate().is_ok());
+
+        let p256 =
+            EncryptionKeyConfig::P256(P256(Sec1Point::Compressed(decode(P256_G_COMPRESSED))));
+        assert!(p256.validate().is_ok());
+
+        // Flipping a bit of the y-coordinate moves the point off the curve
+        let mut not_on_curve = decode::<65>(SECP256K1_G_UNCOMPRESSED);
+        not_on_curve[64] ^= 1;
+        let secp256k1 =
+            EncryptionKeyConfig::Secp256k1(Secp256k1(Sec1Point::Uncompressed(not_on_curve)));
+        assert!(matches!(
+            secp256k1.validate(),
+            Err(KeyringError::InvalidKey)
+        ));
+        let p256 = EncryptionKeyConfig::P256(P256(Sec1Point::Uncompressed(decode(
+            SECP256K1_G_UNCOMPRESSED,
+        ))));
+        assert!(matches!(p256.validate(), Err(KeyringError::InvalidKey)));
+    }
+}
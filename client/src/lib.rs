@@ -3,6 +3,14 @@
 #![deny(missing_docs)]
 #![cfg_attr(not(test), forbid(unsafe_code))]
 
+pub mod blocking;
+pub mod envelope;
 pub mod error;
+pub mod hpke;
+pub mod instruction;
 pub mod keyring;
 pub mod keystore;
+pub mod reader;
+pub mod sealed_box;
+pub mod simulation;
+pub mod x25519;
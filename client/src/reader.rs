@@ -0,0 +1,273 @@
+//! Read-only access to keyrings
+//!
+//! Services that only look up keys don't send transactions, so they don't
+//! need a payer.
+
+use {
+    crate::{
+        envelope::Envelope,
+        error::KeyringError,
+        hpke,
+        keystore::{AeKey, ElGamal, Fingerprint, KeyAlgorithm, Keystore, KeystoreEntry},
+        sealed_box,
+    },
+    borsh::BorshDeserialize,
+    futures_util::future::join_all,
+    solana_sdk::{account::Account, clock::UnixTimestamp, pubkey::Pubkey},
+    spl_token_client::client::{ProgramClient, SendTransaction},
+    std::{
+        collections::HashMap,
+        fmt,
+        sync::Arc,
+        time::{SystemTime, UNIX_EPOCH},
+    },
+};
+
+/// Number of keyring accounts fetched concurrently
+///
+/// `ProgramClient` fetches one account per request, so this caps the
+/// requests in flight rather than batching accounts into a single call.
+const MAX_FETCH_CHUNK_LEN: usize = 100;
+
+/// The local clock as a Unix timestamp, for checking key expiry
+fn now() -> UnixTimestamp {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs() as UnixTimestamp)
+}
+
+/// Read-only Keyring Program Client
+///
+/// Offers every query of `Keyring` without a payer. `Keyring` wraps one,
+/// see `Keyring::reader`.
+pub struct KeyringReader<T> {
+    pub(crate) client: Arc<dyn ProgramClient<T>>,
+    pub(crate) program_id: Pubkey,
+}
+
+impl<T> Clone for KeyringReader<T> {
+    fn clone(&self) -> Self {
+        Self {
+            client: Arc::clone(&self.client),
+            program_id: self.program_id,
+        }
+    }
+}
+
+impl<T> fmt::Debug for KeyringReader<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeyringReader")
+            .field("program_id", &self.program_id)
+            .finish()
+    }
+}
+
+impl<T> KeyringReader<T>
+where
+    T: SendTransaction,
+{
+    /// Create a new read-only client
+    pub fn new(client: Arc<dyn ProgramClient<T>>, program_id: &Pubkey) -> Self {
+        Self {
+            client,
+            program_id: *program_id,
+        }
+    }
+
+    /// Get the users's keyring address
+    pub fn get_keyring_address(&self, authority: &Pubkey) -> (Pubkey, u8) {
+        spl_keyring_program::state::Keyring::pda(&self.program_id, authority)
+    }
+
+    /// Fetch the user's keyring account
+    pub async fn get_keyring_account(&self, authority: &Pubkey) -> Result<Account, KeyringError> {
+        self.client
+            .get_account(self.get_keyring_address(authority).0)
+            .await
+            .map_err(KeyringError::Client)?
+            .ok_or(KeyringError::KeystoreNotFound)
+    }
+
+    /// Fetch the user's keyring account, unpacked
+    ///
+    /// Fails with `KeyringError::IncorrectOwner` if the account isn't owned
+    /// by the configured keyring program.
+    pub async fn get_keystore(&self, authority: &Pubkey) -> Result<Keystore, KeyringError> {
+        let keyring_account = self.get_keyring_account(authority).await?;
+        self.unpack_keystore(&keyring_account)
+    }
+
+    /// Unpack a keyring account fetched with `get_keyring_account`
+    pub(crate) fn unpack_keystore(
+        &self,
+        keyring_account: &Account,
+    ) -> Result<Keystore, KeyringError> {
+        if keyring_account.owner != self.program_id {
+            return Err(KeyringError::IncorrectOwner);
+        }
+        if keyring_account.data.is_empty() {
+            Ok(Keystore::default())
+        } else {
+            Keystore::try_from_slice(&keyring_account.data)
+                .map_err(|_| KeyringError::InvalidKeystore)
+        }
+    }
+
+    /// Fetch the keystores of many users
+    ///
+    /// Keyring accounts are fetched with one request each, at most 100 at a
+    /// time. Each user maps to their keystore, to
+    /// `KeyringError::KeystoreNotFound` if they have no keyring, to
+    /// `KeyringError::IncorrectOwner` if the account isn't the keyring
+    /// program's, or to `KeyringError::InvalidKeystore` if their keyring does
+    /// not hold a valid keystore.
+    pub async fn get_keystores(
+        &self,
+        authorities: &[Pubkey],
+    ) -> HashMap<Pubkey, Result<Keystore, KeyringError>> {
+        authorities
+            .iter()
+            .copied()
+            .zip(self.fetch_keystores(authorities).await)
+            .collect()
+    }
+
+    /// Fetch keystores in concurrent chunks, in the order of `authorities`
+    async fn fetch_keystores(&self, authorities: &[Pubkey]) -> Vec<Result<Keystore, KeyringError>> {
+        let mut keystores = Vec::with_capacity(authorities.len());
+        for chunk in authorities.chunks(MAX_FETCH_CHUNK_LEN) {
+            keystores.extend(join_all(chunk.iter().map(|a| self.get_keystore(a))).await);
+        }
+        keystores
+    }
+
+    /// Fetch the entries of a user's keystore holding keys of the given
+    /// algorithm, in keystore order
+    ///
+    /// The result is empty if the user has published no such key.
+    pub async fn get_entries_by_algorithm(
+        &self,
+        authority: &Pubkey,
+        algorithm: KeyAlgorithm,
+    ) -> Result<Vec<KeystoreEntry>, KeyringError> {
+        Ok(self
+            .get_keystore(authority)
+            .await?
+            .entries_by_algorithm(algorithm)
+            .cloned()
+            .collect())
+    }
+
+    /// Fetch the entry of a user's keystore whose key has the given
+    /// fingerprint
+    pub async fn get_entry_by_fingerprint(
+        &self,
+        authority: &Pubkey,
+        fingerprint: &Fingerprint,
+    ) -> Result<KeystoreEntry, KeyringError> {
+        self.get_keystore(authority)
+            .await?
+            .find_by_fingerprint(fingerprint)
+            .cloned()
+            .ok_or(KeyringError::EntryNotFound)
+    }
+
+    /// Pick the recipient's entry to encrypt to, given the algorithms the
+    /// sender supports in order of preference
+    ///
+    /// See `Keystore::negotiate`. Like every key lookup, this checks expiry
+    /// against the local clock.
+    pub async fn negotiate(
+        &self,
+        recipient: &Pubkey,
+        supported: &[KeyAlgorithm],
+    ) -> Result<KeystoreEntry, KeyringError> {
+        self.get_keystore(recipient)
+            .await?
+            .negotiate(supported, now())
+            .cloned()
+    }
+
+    /// Fetch the ElGamal public key a recipient has published for
+    /// confidential transfers of the given mint
+    pub async fn get_elgamal_pubkey(
+        &self,
+        authority: &Pubkey,
+        mint: &Pubkey,
+    ) -> Result<ElGamal, KeyringError> {
+        self.get_keystore(authority)
+            .await?
+            .elgamal_pubkey(mint, now())
+            .cloned()
+            .ok_or(KeyringError::EntryNotFound)
+    }
+
+    /// Fetch the AE key commitment a user has published for confidential
+    /// transfers of the given mint
+    pub async fn get_ae_key(
+        &self,
+        authority: &Pubkey,
+        mint: &Pubkey,
+    ) -> Result<AeKey, KeyringError> {
+        self.get_keystore(authority)
+            .await?
+            .ae_key(mint, now())
+            .cloned()
+            .ok_or(KeyringError::EntryNotFound)
+    }
+
+    /// Encrypt a message to a wallet using the Curve25519 key published in
+    /// its keystore
+    ///
+    /// The result can be opened with `sealed_box::open` and the recipient's
+    /// X25519 secret key.
+    pub async fn seal(&self, recipient: &Pubkey, message: &[u8]) -> Result<Vec<u8>, KeyringError> {
+        let key = self
+            .get_keystore(recipient)
+            .await?
+            .curve25519_key(recipient, now())
+            .ok_or(KeyringError::EntryNotFound)?;
+        sealed_box::seal(&key, message)
+    }
+
+    /// Encrypt a message to a wallet with HPKE, using the cipher suite the
+    /// wallet advertised in its keystore
+    ///
+    /// Returns the encapsulated key and the ciphertext, which the recipient
+    /// opens with `hpke::open`.
+    pub async fn hpke_seal(
+        &self,
+        recipient: &Pubkey,
+        info: &[u8],
+        aad: &[u8],
+        plaintext: &[u8],
+    ) -> Result<(Vec<u8>, Vec<u8>), KeyringError> {
+        let keystore = self.get_keystore(recipient).await?;
+        let config = keystore.hpke(now()).ok_or(KeyringError::EntryNotFound)?;
+        hpke::seal(config, info, aad, plaintext)
+    }
+
+    /// Encrypt a message for a group of wallets
+    ///
+    /// Every recipient must publish a usable key. The envelope's headers
+    /// follow the order of `recipients`, and if several recipients can't be
+    /// encrypted to, the error is the first one's. Keystores are fetched as
+    /// in `get_keystores`.
+    pub async fn seal_envelope(
+        &self,
+        recipients: &[Pubkey],
+        plaintext: &[u8],
+    ) -> Result<Envelope, KeyringError> {
+        let now = now();
+        let keys = recipients
+            .iter()
+            .zip(self.fetch_keystores(recipients).await)
+            .map(|(recipient, keystore)| {
+                keystore?
+                    .envelope_key(recipient, now)
+                    .ok_or(KeyringError::EntryNotFound)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Envelope::seal(&keys, plaintext)
+    }
+}
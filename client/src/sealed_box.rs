@@ -0,0 +1,137 @@
+//! Anonymous public-key encryption to a Curve25519 key
+//!
+//! A sealed box is the ephemeral X25519 public key followed by the
+//! XChaCha20-Poly1305 ciphertext. The symmetric key and nonce are derived
+//! with HKDF-SHA256 from the X25519 shared secret, salted with both public
+//! keys. Since every box uses a fresh ephemeral key, the sender cannot open
+//! it again and the recipient learns nothing about who sealed it.
+
+use {
+    crate::error::KeyringError,
+    chacha20poly1305::{
+        aead::{Aead, NewAead},
+        Key, XChaCha20Poly1305, XNonce,
+    },
+    hkdf::Hkdf,
+    rand::rngs::OsRng,
+    sha2::Sha256,
+    x25519_dalek::{EphemeralSecret, PublicKey, SharedSecret, StaticSecret},
+};
+
+/// Domain separator for the sealed box key derivation
+const INFO: &[u8] = b"spl-keyring:sealed-box";
+
+/// Length of the ephemeral public key prefixed to every sealed box
+pub const PUBLIC_KEY_LEN: usize = 32;
+
+/// Length of the Poly1305 authentication tag
+pub const TAG_LEN: usize = 16;
+
+/// Encrypt a message to the given recipient key
+pub fn seal(recipient: &PublicKey, message: &[u8]) -> Result<Vec<u8>, KeyringError> {
+    let ephemeral_secret = EphemeralSecret::new(OsRng);
+    let ephemeral_public = PublicKey::from(&ephemeral_secret);
+    let shared = ephemeral_secret.diffie_hellman(recipient);
+
+    let (cipher, nonce) = derive_cipher(&shared, &ephemeral_public, recipient)?;
+    let ciphertext = cipher
+        .encrypt(&nonce, message)
+        .map_err(|_| KeyringError::EncryptionFailed)?;
+
+    let mut sealed = Vec::with_capacity(PUBLIC_KEY_LEN + ciphertext.len());
+    sealed.extend_from_slice(ephemeral_public.as_bytes());
+    sealed.extend_from_slice(&ciphertext);
+    Ok(sealed)
+}
+
+/// Decrypt a sealed box with the recipient's secret key
+pub fn open(recipient: &StaticSecret, sealed: &[u8]) -> Result<Vec<u8>, KeyringError> {
+    if sealed.len() < PUBLIC_KEY_LEN + TAG_LEN {
+        return Err(KeyringError::DecryptionFailed);
+    }
+    let (ephemeral_public, ciphertext) = sealed.split_at(PUBLIC_KEY_LEN);
+    let mut ephemeral_bytes = [0u8; PUBLIC_KEY_LEN];
+    ephemeral_bytes.copy_from_slice(ephemeral_public);
+    let ephemeral_public = PublicKey::from(ephemeral_bytes);
+
+    let shared = recipient.diffie_hellman(&ephemeral_public);
+    let (cipher, nonce) = derive_cipher(&shared, &ephemeral_public, &PublicKey::from(recipient))
+        .map_err(|_| KeyringError::DecryptionFailed)?;
+    cipher
+        .decrypt(&nonce, ciphertext)
+        .map_err(|_| KeyringError::DecryptionFailed)
+}
+
+fn derive_cipher(
+    shared: &SharedSecret,
+    ephemeral_public: &PublicKey,
+    recipient: &PublicKey,
+) -> Result<(XChaCha20Poly1305, XNonce), KeyringError> {
+    // A low-order recipient key yields an all-zero shared secret
+    if shared.as_bytes() == &[0u8; 32] {
+        return Err(KeyringError::InvalidKey);
+    }
+
+    let mut salt = [0u8; 64];
+    salt[..32].copy_from_slice(ephemeral_public.as_bytes());
+    salt[32..].copy_from_slice(recipient.as_bytes());
+
+    let mut okm = [0u8; 56];
+    Hkdf::<Sha256>::new(Some(&salt), shared.as_bytes())
+        .expand(INFO, &mut okm)
+        .map_err(|_| KeyringError::EncryptionFailed)?;
+
+    let cipher = XChaCha20Poly1305::new(Key::from_slice(&okm[..32]));
+    let nonce = *XNonce::from_slice(&okm[32..]);
+    Ok((cipher, nonce))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seal_open() {
+        let secret = StaticSecret::new(OsRng);
+        let message = b"hello keyring";
+
+        let sealed = seal(&PublicKey::from(&secret), message).unwrap();
+        assert_eq!(sealed.len(), PUBLIC_KEY_LEN + message.len() + TAG_LEN);
+        assert_eq!(open(&secret, &sealed).unwrap(), message);
+    }
+
+    #[test]
+    fn test_open_wrong_key() {
+        let secret = StaticSecret::new(OsRng);
+        let sealed = seal(&PublicKey::from(&secret), b"hello keyring").unwrap();
+
+        let wrong_secret = StaticSecret::new(OsRng);
+        assert!(matches!(
+            open(&wrong_secret, &sealed),
+            Err(KeyringError::DecryptionFailed)
+        ));
+    }
+
+    #[test]
+    fn test_open_tampered() {
+        let secret = StaticSecret::new(OsRng);
+        let mut sealed = seal(&PublicKey::from(&secret), b"hello keyring").unwrap();
+        *sealed.last_mut().unwrap() ^= 1;
+        assert!(matches!(
+            open(&secret, &sealed),
+            Err(KeyringError::DecryptionFailed)
+        ));
+        assert!(matches!(
+            open(&secret, &sealed[..PUBLIC_KEY_LEN]),
+            Err(KeyringError::DecryptionFailed)
+        ));
+    }
+
+    #[test]
+    fn test_seal_low_order_key() {
+        assert!(matches!(
+            seal(&PublicKey::from([0u8; 32]), b"hello keyring"),
+            Err(KeyringError::InvalidKey)
+        ));
+    }
+}
@@ -0,0 +1,222 @@
+//! Transaction simulation
+//!
+//! `ProgramClient` can't simulate transactions, so the client takes a
+//! separate simulator for estimating compute units and dry runs.
+
+use {
+    crate::keyring::MAX_COMPUTE_UNIT_LIMIT,
+    async_trait::async_trait,
+    borsh::BorshDeserialize,
+    solana_rpc_client::nonblocking::rpc_client::RpcClient,
+    solana_rpc_client_api::{
+        config::RpcSimulateTransactionConfig, response::RpcSimulateTransactionResult,
+    },
+    solana_sdk::{
+        compute_budget::{self, ComputeBudgetInstruction},
+        message::VersionedMessage,
+        transaction::{Transaction, TransactionError, VersionedTransaction},
+    },
+    spl_token_client::client::ProgramClientError,
+};
+
+/// Lamports charged per transaction signature
+pub const LAMPORTS_PER_SIGNATURE: u64 = 5_000;
+
+/// Compute-unit limit the runtime grants each instruction when a
+/// transaction doesn't request one
+const DEFAULT_INSTRUCTION_COMPUTE_UNIT_LIMIT: u32 = 200_000;
+
+/// Outcome of simulating a transaction
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SimulationResult {
+    /// The error the transaction failed with, if any
+    pub err: Option<TransactionError>,
+    /// Program logs
+    pub logs: Vec<String>,
+    /// Compute units consumed
+    pub units_consumed: u64,
+}
+
+/// Runs transactions without committing them, such as an RPC node's
+/// `simulateTransaction` or a `BanksClient`
+///
+/// Implemented for the nonblocking `RpcClient`.
+///
+/// Transactions passed to the simulator are unsigned, so it must not verify
+/// signatures.
+#[async_trait]
+pub trait SimulateTransaction: Send + Sync {
+    /// Simulate a legacy transaction
+    async fn simulate_transaction(
+        &self,
+        transaction: &Transaction,
+    ) -> Result<SimulationResult, ProgramClientError>;
+
+    /// Simulate a legacy or v0 transaction
+    ///
+    /// The client always simulates through this method. By default, legacy
+    /// transactions go to `simulate_transaction` and v0 transactions are
+    /// rejected; simulators used with `Keyring::with_versioned_transactions`
+    /// must override it.
+    async fn simulate_versioned_transaction(
+        &self,
+        transaction: &VersionedTransaction,
+    ) -> Result<SimulationResult, ProgramClientError> {
+        let transaction = transaction
+            .clone()
+            .into_legacy_transaction()
+            .ok_or("simulator does not support v0 transactions")?;
+        self.simulate_transaction(&transaction).await
+    }
+}
+
+/// Simulates through an RPC node's `simulateTransaction`, at the client's
+/// commitment and without verifying signatures
+#[async_trait]
+impl SimulateTransaction for RpcClient {
+    async fn simulate_transaction(
+        &self,
+        transaction: &Transaction,
+    ) -> Result<SimulationResult, ProgramClientError> {
+        let result = self
+            .simulate_transaction_with_config(transaction, rpc_simulate_config(self))
+            .await?;
+        Ok(result.value.into())
+    }
+
+    async fn simulate_versioned_transaction(
+        &self,
+        transaction: &VersionedTransaction,
+    ) -> Result<SimulationResult, ProgramClientError> {
+        let result = self
+            .simulate_transaction_with_config(transaction, rpc_simulate_config(self))
+            .await?;
+        Ok(result.value.into())
+    }
+}
+
+fn rpc_simulate_config(client: &RpcClient) -> RpcSimulateTransactionConfig {
+    RpcSimulateTransactionConfig {
+        sig_verify: false,
+        commitment: Some(client.commitment()),
+        ..RpcSimulateTransactionConfig::default()
+    }
+}
+
+impl From<RpcSimulateTransactionResult> for SimulationResult {
+    fn from(result: RpcSimulateTransactionResult) -> Self {
+        Self {
+            err: result.err,
+            logs: result.logs.unwrap_or_default(),
+            units_consumed: result.units_consumed.unwrap_or_default(),
+        }
+    }
+}
+
+/// Report of a keyring operation run through the simulator instead of
+/// being sent
+///
+/// Large keystores are written in several transactions. Each one is
+/// simulated on its own against the current state, not on top of the
+/// transactions before it, and the report sums them up. Since every chunk
+/// writes its own range of the account, this catches the same errors
+/// sending them would, but the logs and compute units of a later chunk come
+/// from a run that did not see the earlier ones. A staged rotation's commit
+/// reads data the earlier transactions stage, so it isn't simulated: it
+/// only adds to the transaction count and the estimated fee.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SimulationReport {
+    /// The first error a transaction failed with, if any
+    pub err: Option<TransactionError>,
+    /// Program logs of every transaction, in order
+    pub logs: Vec<String>,
+    /// Compute units consumed
+    pub units_consumed: u64,
+    /// Number of transactions the operation sends
+    pub transactions: usize,
+    /// Size of the keyring account before the operation
+    pub len_before: usize,
+    /// Size of the keyring account after the operation
+    pub len_after: usize,
+    /// Estimated transaction fees, priority fees included
+    pub fee: u64,
+    /// Lamports the keyring account lacks to stay rent-exempt at its new
+    /// size
+    pub rent: u64,
+}
+
+impl SimulationReport {
+    /// Lamports needed to carry out the operation: the fees plus the rent
+    pub fn lamports_required(&self) -> u64 {
+        self.fee.saturating_add(self.rent)
+    }
+}
+
+/// Estimate the fee of a message from its signatures and compute budget
+pub(crate) fn estimate_fee(message: &VersionedMessage) -> u64 {
+    let mut limit = None;
+    let mut price = 0;
+    let mut instructions = 0;
+    for ix in message.instructions() {
+        let program_id = message
+            .static_account_keys()
+            .get(usize::from(ix.program_id_index));
+        if program_id != Some(&compute_budget::id()) {
+            instructions += 1;
+            continue;
+        }
+        match ComputeBudgetInstruction::try_from_slice(&ix.data) {
+            Ok(ComputeBudgetInstruction::SetComputeUnitLimit(units)) => limit = Some(units),
+            Ok(ComputeBudgetInstruction::SetComputeUnitPrice(micro_lamports)) => {
+                price = micro_lamports
+            }
+            _ => {}
+        }
+    }
+    let limit = limit
+        .unwrap_or(DEFAULT_INSTRUCTION_COMPUTE_UNIT_LIMIT.saturating_mul(instructions))
+        .min(MAX_COMPUTE_UNIT_LIMIT);
+
+    let signatures = u64::from(message.header().num_required_signatures);
+    let priority_fee = (u128::from(price) * u128::from(limit)).div_ceil(1_000_000);
+    signatures
+        .saturating_mul(LAMPORTS_PER_SIGNATURE)
+        .saturating_add(u64::try_from(priority_fee).unwrap_or(u64::MAX))
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        solana_sdk::{instruction::Instruction, message::Message, pubkey::Pubkey},
+    };
+
+    #[test]
+    fn test_estimate_fee() {
+        let payer = Pubkey::new_unique();
+        let program_id = Pubkey::new_unique();
+        let ix = Instruction::new_with_bytes(program_id, &[], vec![]);
+        let message = |instructions: &[Instruction]| {
+            VersionedMessage::Legacy(Message::new(instructions, Some(&payer)))
+        };
+
+        assert_eq!(
+            estimate_fee(&message(std::slice::from_ref(&ix))),
+            LAMPORTS_PER_SIGNATURE
+        );
+
+        // Without a limit, the price applies to the default per-instruction limit
+        let price = ComputeBudgetInstruction::set_compute_unit_price(1_000_000);
+        assert_eq!(
+            estimate_fee(&message(&[price.clone(), ix.clone(), ix.clone()])),
+            LAMPORTS_PER_SIGNATURE + 400_000
+        );
+
+        let limit = ComputeBudgetInstruction::set_compute_unit_limit(1_001);
+        let price = ComputeBudgetInstruction::set_compute_unit_price(1_000);
+        assert_eq!(
+            estimate_fee(&message(&[limit, price, ix])),
+            LAMPORTS_PER_SIGNATURE + 2
+        );
+    }
+}
@@ -0,0 +1,100 @@
+//! Conversion of Ed25519 wallet keys into X25519 encryption keys
+//!
+//! Ed25519 and X25519 keys live on birationally equivalent curves, so a
+//! wallet's signing keypair can double as a Curve25519 encryption keypair
+//! without publishing any extra key material. This uses the same conversion
+//! as libsodium's `crypto_sign_ed25519_pk_to_curve25519` and
+//! `crypto_sign_ed25519_sk_to_curve25519`.
+
+use {
+    crate::error::KeyringError,
+    curve25519_dalek::edwards::CompressedEdwardsY,
+    sha2::{Digest, Sha512},
+    solana_sdk::{pubkey::Pubkey, signature::Keypair},
+    x25519_dalek::{PublicKey, StaticSecret},
+};
+
+/// Convert a wallet address into its X25519 public key
+///
+/// Fails if the address is not a valid Ed25519 point, which is the case for
+/// program-derived addresses.
+pub fn public_key_from_wallet(wallet: &Pubkey) -> Result<PublicKey, KeyringError> {
+    let point = CompressedEdwardsY(wallet.to_bytes())
+        .decompress()
+        .ok_or(KeyringError::InvalidKey)?;
+    Ok(PublicKey::from(point.to_montgomery().to_bytes()))
+}
+
+/// Convert a wallet keypair into its X25519 secret key
+///
+/// The secret scalar is the first half of the SHA-512 hash of the Ed25519
+/// seed, exactly as used for signing. Clamping is applied by X25519 itself.
+pub fn secret_key_from_wallet(wallet: &Keypair) -> StaticSecret {
+    let hash = Sha512::digest(&wallet.to_bytes()[..32]);
+    let mut scalar = [0u8; 32];
+    scalar.copy_from_slice(&hash[..32]);
+    StaticSecret::from(scalar)
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        solana_sdk::signer::{keypair::keypair_from_seed, Signer},
+    };
+
+    fn decode(hex: &str) -> [u8; 32] {
+        let mut bytes = [0u8; 32];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).unwrap();
+        }
+        bytes
+    }
+
+    #[test]
+    fn test_libsodium_vector() {
+        // From libsodium's `ed25519_convert` test
+        let seed = decode("421151a459faeade3d247115f94aedae42318124095afabe4d1451a559faedee");
+        let wallet = keypair_from_seed(&seed).unwrap();
+        assert_eq!(
+            wallet.pubkey().to_bytes(),
+            decode("b5076a8474a832daee4dd5b4040983b6623b5f344aca57d4d6ee4baf3f259e6e")
+        );
+        assert_eq!(
+            public_key_from_wallet(&wallet.pubkey()).unwrap().to_bytes(),
+            decode("f1814f0e8ff1043d8a44d25babff3cedcae6c22c3edaa48f857ae70de2baae50")
+        );
+        assert_eq!(
+            secret_key_from_wallet(&wallet).to_bytes(),
+            decode("8052030376d47112be7f73ed7a019293dd12ad910b654455798b4667d73de166")
+        );
+    }
+
+    #[test]
+    fn test_wallet_keypair_conversion() {
+        let wallet = Keypair::new();
+        let secret = secret_key_from_wallet(&wallet);
+        let public = public_key_from_wallet(&wallet.pubkey()).unwrap();
+        assert_eq!(PublicKey::from(&secret).as_bytes(), public.as_bytes());
+    }
+
+    #[test]
+    fn test_wallet_key_agreement() {
+        let alice = Keypair::new();
+        let bob = Keypair::new();
+        let alice_shared = secret_key_from_wallet(&alice)
+            .diffie_hellman(&public_key_from_wallet(&bob.pubkey()).unwrap());
+        let bob_shared = secret_key_from_wallet(&bob)
+            .diffie_hellman(&public_key_from_wallet(&alice.pubkey()).unwrap());
+        assert_eq!(alice_shared.as_bytes(), bob_shared.as_bytes());
+    }
+
+    #[test]
+    fn test_off_curve_wallet() {
+        let (pda, _) = Pubkey::find_program_address(&[b"keyring"], &Pubkey::new_unique());
+        assert!(matches!(
+            public_key_from_wallet(&pda),
+            Err(KeyringError::InvalidKey)
+        ));
+    }
+}
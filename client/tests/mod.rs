@@ -1,25 +1,51 @@
 use {
+    async_trait::async_trait,
+    borsh::{BorshDeserialize, BorshSerialize},
     solana_program_test::{
         processor,
         tokio::{self, sync::Mutex},
-        ProgramTest,
+        BanksClientError, ProgramTest, ProgramTestContext,
     },
     solana_sdk::{
+        account::Account,
+        address_lookup_table_account::AddressLookupTableAccount,
         borsh::get_instance_packed_len,
-        instruction::Instruction,
+        compute_budget::ComputeBudgetInstruction,
+        hash::Hash,
+        instruction::InstructionError,
+        native_token::LAMPORTS_PER_SOL,
         pubkey::Pubkey,
         rent::Rent,
-        signer::{keypair::Keypair, Signer},
+        signature::Signature,
+        signer::{keypair::Keypair, Signer, SignerError},
         system_instruction,
+        transaction::{Transaction, TransactionError, VersionedTransaction},
     },
     spl_keyring_client::{
-        keyring::Keyring,
-        keystore::{Curve25519, EncryptionKeyConfig, Keystore, Rsa},
+        blocking::{BlockingKeyring, BlockingProgramClient},
+        envelope::Envelope,
+        error::KeyringError,
+        hpke, instruction,
+        keyring::{
+            attach_signature, ComputeUnitLimit, DurableNonce, Keyring, KeystoreChange,
+            SendVersionedTransaction, MAX_STAGING_WRITE_LEN,
+        },
+        keystore::{
+            Curve25519, ElGamal, EncryptionKeyConfig, EntryConfig, EntryMetadata, Hpke, HpkeConfig,
+            KeyAlgorithm, KeyStatus, KeyUsage, Keystore, KeystoreEntry, MlKem, MlKemParameterSet,
+            Rsa,
+        },
+        reader::KeyringReader,
+        sealed_box,
+        simulation::{SimulateTransaction, SimulationResult, LAMPORTS_PER_SIGNATURE},
+        x25519,
     },
+    spl_keyring_program::{error::KeyringProgramError, state::KeyringStaging},
     spl_token_client::client::{
-        ProgramBanksClient, ProgramBanksClientProcessTransaction, ProgramClient,
+        ProgramBanksClient, ProgramBanksClientProcessTransaction, ProgramClient, ProgramClientError,
     },
     std::sync::Arc,
+    x25519_dalek::{PublicKey, StaticSecret},
 };
 
 struct TestContext {
@@ -29,14 +55,19 @@ struct TestContext {
 
 impl TestContext {
     async fn new() -> Self {
-        let program_test = ProgramTest::new(
-            "spl_keyring_program",
-            spl_keyring_program::id(),
-            processor!(spl_keyring_program::processor::process),
-        );
+        Self::with_program_id(spl_keyring_program::id()).await
+    }
+
+    async fn with_program_id(program_id: Pubkey) -> Self {
+        Self::start(program_test(program_id), program_id).await
+    }
+
+    async fn start(program_test: ProgramTest, program_id: Pubkey) -> Self {
         let ctx = program_test.start_with_context().await;
-        let ctx = Arc::new(Mutex::new(ctx));
+        Self::from_context(Arc::new(Mutex::new(ctx)), program_id).await
+    }
 
+    async fn from_context(ctx: Arc<Mutex<ProgramTestContext>>, program_id: Pubkey) -> Self {
         let authority = keypair_clone(&ctx.lock().await.payer);
 
         let client: Arc<dyn ProgramClient<ProgramBanksClientProcessTransaction>> =
@@ -47,7 +78,7 @@ impl TestContext {
 
         let keyring = Keyring::new(
             Arc::clone(&client),
-            &spl_keyring_program::id(),
+            &program_id,
             Arc::new(keypair_clone(&authority)),
         );
 
@@ -55,21 +86,141 @@ impl TestContext {
     }
 }
 
+fn program_test(program_id: Pubkey) -> ProgramTest {
+    ProgramTest::new(
+        "spl_keyring_program",
+        program_id,
+        processor!(spl_keyring_program::processor::process),
+    )
+}
+
+/// A signer that holds its key elsewhere, like a hardware wallet
+struct RemoteSigner {
+    pubkey: Pubkey,
+    device: Keypair,
+}
+
+impl Signer for RemoteSigner {
+    fn try_pubkey(&self) -> Result<Pubkey, SignerError> {
+        Ok(self.pubkey)
+    }
+
+    fn try_sign_message(&self, message: &[u8]) -> Result<Signature, SignerError> {
+        self.device.try_sign_message(message)
+    }
+
+    fn is_interactive(&self) -> bool {
+        true
+    }
+}
+
+/// Simulates transactions against the test bank
+struct BanksSimulator(Arc<Mutex<ProgramTestContext>>);
+
+#[async_trait]
+impl SimulateTransaction for BanksSimulator {
+    async fn simulate_transaction(
+        &self,
+        transaction: &Transaction,
+    ) -> Result<SimulationResult, ProgramClientError> {
+        self.simulate_versioned_transaction(&transaction.clone().into())
+            .await
+    }
+
+    async fn simulate_versioned_transaction(
+        &self,
+        transaction: &VersionedTransaction,
+    ) -> Result<SimulationResult, ProgramClientError> {
+        let simulation = self
+            .0
+            .lock()
+            .await
+            .banks_client
+            .simulate_transaction(transaction.clone())
+            .await?;
+        let details = simulation
+            .simulation_details
+            .ok_or("simulation returned no details")?;
+        Ok(SimulationResult {
+            err: simulation.result.and_then(Result::err),
+            logs: details.logs,
+            units_consumed: details.units_consumed,
+        })
+    }
+}
+
+/// Sends v0 transactions to the test bank
+struct BanksSender(Arc<Mutex<ProgramTestContext>>);
+
+#[async_trait]
+impl SendVersionedTransaction<ProgramBanksClientProcessTransaction> for BanksSender {
+    async fn send_versioned_transaction(
+        &self,
+        transaction: &VersionedTransaction,
+    ) -> Result<(), ProgramClientError> {
+        self.0
+            .lock()
+            .await
+            .banks_client
+            .process_transaction(transaction.clone())
+            .await
+            .map_err(Into::into)
+    }
+}
+
+/// A synchronous backend over the test bank, driving the banks client on a
+/// runtime of its own
+struct BlockingBanksClient {
+    runtime: tokio::runtime::Handle,
+    client: ProgramBanksClient<ProgramBanksClientProcessTransaction>,
+}
+
+impl BlockingProgramClient<ProgramBanksClientProcessTransaction> for BlockingBanksClient {
+    fn get_minimum_balance_for_rent_exemption(
+        &self,
+        data_len: usize,
+    ) -> Result<u64, ProgramClientError> {
+        self.runtime
+            .block_on(self.client.get_minimum_balance_for_rent_exemption(data_len))
+    }
+
+    fn get_latest_blockhash(&self) -> Result<Hash, ProgramClientError> {
+        self.runtime.block_on(self.client.get_latest_blockhash())
+    }
+
+    fn send_transaction(&self, transaction: &Transaction) -> Result<(), ProgramClientError> {
+        self.runtime
+            .block_on(self.client.send_transaction(transaction))
+    }
+
+    fn get_account(&self, address: Pubkey) -> Result<Option<Account>, ProgramClientError> {
+        self.runtime.block_on(self.client.get_account(address))
+    }
+}
+
 fn keypair_clone(kp: &Keypair) -> Keypair {
     Keypair::from_bytes(&kp.to_bytes()).expect("failed to copy keypair")
 }
 
-fn get_fund_rent_instruction(
+/// Create a keyring for `authority` holding `entries`
+async fn create_keyring_with_entries(
     keyring: &Keyring<ProgramBanksClientProcessTransaction>,
-    authority: &Pubkey,
-    new_space: usize,
-) -> Instruction {
-    let lamports = Rent::default().minimum_balance(new_space);
-    system_instruction::transfer(
-        authority,
-        &keyring.get_keyring_address(authority).0,
-        lamports,
-    )
+    authority: &dyn Signer,
+    entries: impl IntoIterator<Item = KeystoreEntry>,
+) {
+    keyring
+        .create_keyring(authority)
+        .await
+        .expect("Failed to create keyring");
+
+    let mut keystore = Keystore::default();
+    for entry in entries {
+        keystore.add(entry);
+    }
+    keyring
+        .write_keystore(authority, &keystore)
+        .await
+        .expect("Failed to write keystore");
 }
 
 #[tokio::test]
@@ -101,19 +252,6 @@ async fn can_add_key() {
 
     let new_key = EncryptionKeyConfig::Curve25519(Curve25519(Pubkey::new_unique().to_bytes()));
 
-    // Fund rent for realloc
-    keyring
-        .process_ixs(
-            &[get_fund_rent_instruction(
-                &keyring,
-                &authority.pubkey(),
-                get_instance_packed_len(&new_key).unwrap(),
-            )],
-            &[&authority],
-        )
-        .await
-        .expect("Failed to fund rent");
-
     // Add an entry to the keystore
     keyring
         .add_entry(&authority, new_key.clone())
@@ -132,7 +270,7 @@ async fn can_add_key() {
         .get_keystore(&authority.pubkey())
         .await
         .expect("Failed to fetch keyring");
-    let mock_keystore = Keystore(vec![new_key]);
+    let mock_keystore = Keystore(vec![new_key.into()]);
     assert_eq!(keystore, mock_keystore);
 }
 
@@ -148,19 +286,6 @@ async fn can_add_multiple_keys() {
 
     let curve_key = EncryptionKeyConfig::Curve25519(Curve25519(Pubkey::new_unique().to_bytes()));
 
-    // Fund rent for realloc
-    keyring
-        .process_ixs(
-            &[get_fund_rent_instruction(
-                &keyring,
-                &authority.pubkey(),
-                get_instance_packed_len(&curve_key).unwrap(),
-            )],
-            &[&authority],
-        )
-        .await
-        .expect("Failed to fund rent");
-
     // Add an entry to the keystore
     keyring
         .add_entry(&authority, curve_key.clone())
@@ -172,19 +297,6 @@ async fn can_add_multiple_keys() {
         .copy_from_slice(&[Pubkey::new_unique().as_ref(), Pubkey::new_unique().as_ref()].concat());
     let rsa_key = EncryptionKeyConfig::Rsa(Rsa(fake_rsa_key_bytes));
 
-    // Fund rent for realloc
-    keyring
-        .process_ixs(
-            &[get_fund_rent_instruction(
-                &keyring,
-                &authority.pubkey(),
-                get_instance_packed_len(&rsa_key).unwrap(),
-            )],
-            &[&authority],
-        )
-        .await
-        .expect("Failed to fund rent");
-
     // Add another entry to the keystore
     keyring
         .add_entry(&authority, rsa_key.clone())
@@ -203,7 +315,7 @@ async fn can_add_multiple_keys() {
         .get_keystore(&authority.pubkey())
         .await
         .expect("Failed to fetch keyring");
-    let mock_keystore = Keystore(vec![curve_key, rsa_key]);
+    let mock_keystore = Keystore(vec![curve_key.into(), rsa_key.into()]);
     assert_eq!(keystore, mock_keystore);
 }
 
@@ -211,56 +323,19 @@ async fn can_add_multiple_keys() {
 async fn can_remove_key() {
     let TestContext { keyring, authority } = TestContext::new().await;
 
-    // Create a keyring
-    keyring
-        .create_keyring(&authority)
-        .await
-        .expect("Failed to create keyring");
-
     let curve_key = EncryptionKeyConfig::Curve25519(Curve25519(Pubkey::new_unique().to_bytes()));
-
-    // Fund rent for realloc
-    keyring
-        .process_ixs(
-            &[get_fund_rent_instruction(
-                &keyring,
-                &authority.pubkey(),
-                get_instance_packed_len(&curve_key).unwrap(),
-            )],
-            &[&authority],
-        )
-        .await
-        .expect("Failed to fund rent");
-
-    // Add an entry to the keystore
-    keyring
-        .add_entry(&authority, curve_key.clone())
-        .await
-        .expect("Failed to add key");
-
     let mut fake_rsa_key_bytes = [0u8; 64];
     fake_rsa_key_bytes
         .copy_from_slice(&[Pubkey::new_unique().as_ref(), Pubkey::new_unique().as_ref()].concat());
     let rsa_key = EncryptionKeyConfig::Rsa(Rsa(fake_rsa_key_bytes));
 
-    // Fund rent for realloc
-    keyring
-        .process_ixs(
-            &[get_fund_rent_instruction(
-                &keyring,
-                &authority.pubkey(),
-                get_instance_packed_len(&rsa_key).unwrap(),
-            )],
-            &[&authority],
-        )
-        .await
-        .expect("Failed to fund rent");
-
-    // Add another entry to the keystore
-    keyring
-        .add_entry(&authority, rsa_key.clone())
-        .await
-        .expect("Failed to add key");
+    // Create a keyring holding both keys
+    create_keyring_with_entries(
+        &keyring,
+        &authority,
+        [curve_key.clone().into(), rsa_key.clone().into()],
+    )
+    .await;
 
     // Manually grabbing account to check buffer length
     let keyring_account = keyring
@@ -272,7 +347,7 @@ async fn can_remove_key() {
 
     // Remove an entry from the keystore
     keyring
-        .remove_entry(&authority, curve_key)
+        .remove_entry(&authority, curve_key.clone())
         .await
         .expect("Failed to remove key");
 
@@ -289,6 +364,1918 @@ async fn can_remove_key() {
         .get_keystore(&authority.pubkey())
         .await
         .expect("Failed to fetch keyring");
-    let mock_keystore = Keystore(vec![rsa_key]);
+    let mock_keystore = Keystore(vec![rsa_key.into()]);
+    assert_eq!(keystore, mock_keystore);
+
+    // Removing it again fails
+    assert!(matches!(
+        keyring.remove_entry(&authority, curve_key).await,
+        Err(KeyringError::EntryNotFound)
+    ));
+}
+
+#[tokio::test]
+async fn can_remove_multiple_keys() {
+    let TestContext { keyring, authority } = TestContext::new().await;
+
+    let curve_key = EncryptionKeyConfig::Curve25519(Curve25519(Pubkey::new_unique().to_bytes()));
+    let rsa_key = EncryptionKeyConfig::Rsa(Rsa([5; 64]));
+    let missing_key = EncryptionKeyConfig::Curve25519(Curve25519(Pubkey::new_unique().to_bytes()));
+
+    // Create a keyring holding both keys
+    create_keyring_with_entries(
+        &keyring,
+        &authority,
+        [curve_key.clone().into(), rsa_key.clone().into()],
+    )
+    .await;
+
+    // Remove both keys, along with one the keystore never held
+    let (missing, write) = keyring
+        .remove_entries(&authority, &[curve_key, missing_key.clone(), rsa_key])
+        .await
+        .expect("Failed to remove keys");
+    assert_eq!(missing, vec![missing_key]);
+    assert_eq!(write.outputs.len(), 1);
+
+    let keystore = keyring
+        .get_keystore(&authority.pubkey())
+        .await
+        .expect("Failed to fetch keyring");
+    assert_eq!(keystore, Keystore::default());
+}
+
+#[tokio::test]
+async fn can_fetch_elgamal_pubkey_for_mint() {
+    let TestContext { keyring, authority } = TestContext::new().await;
+
+    let mint = Pubkey::new_unique();
+    let untagged_key = ElGamal {
+        pubkey: Pubkey::new_unique().to_bytes(),
+        mint: None,
+    };
+    let mint_key = ElGamal {
+        pubkey: Pubkey::new_unique().to_bytes(),
+        mint: Some(mint),
+    };
+
+    // Create a keyring holding both keys
+    create_keyring_with_entries(
+        &keyring,
+        &authority,
+        [untagged_key.clone(), mint_key.clone()]
+            .map(|key| EncryptionKeyConfig::ElGamal(key).into()),
+    )
+    .await;
+
+    // The mint-tagged key wins for its own mint
+    let elgamal_pubkey = keyring
+        .get_elgamal_pubkey(&authority.pubkey(), &mint)
+        .await
+        .expect("Failed to fetch ElGamal pubkey");
+    assert_eq!(elgamal_pubkey, mint_key);
+
+    // Any other mint falls back to the untagged key
+    let elgamal_pubkey = keyring
+        .get_elgamal_pubkey(&authority.pubkey(), &Pubkey::new_unique())
+        .await
+        .expect("Failed to fetch ElGamal pubkey");
+    assert_eq!(elgamal_pubkey, untagged_key);
+
+    // No AE key commitment was published
+    let err = keyring
+        .get_ae_key(&authority.pubkey(), &mint)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, KeyringError::EntryNotFound));
+}
+
+#[tokio::test]
+async fn can_add_ml_kem_key() {
+    let TestContext { keyring, authority } = TestContext::new().await;
+
+    // Create a keyring
+    keyring
+        .create_keyring(&authority)
+        .await
+        .expect("Failed to create keyring");
+
+    // ML-KEM-1024 keys are too large to fit in a single transaction
+    let key_bytes = (0..MlKemParameterSet::MlKem1024.encapsulation_key_len())
+        .map(|i| i as u8)
+        .collect();
+    let ml_kem_key = EncryptionKeyConfig::MlKem(
+        MlKem::new(MlKemParameterSet::MlKem1024, key_bytes).expect("Invalid ML-KEM key"),
+    );
+
+    // Add an entry to the keystore, in two chunks
+    let write = keyring
+        .add_entry(&authority, ml_kem_key.clone())
+        .await
+        .expect("Failed to add key");
+    assert_eq!(write.outputs.len(), 2);
+
+    // Manually grabbing account to check buffer length
+    let keyring_account = keyring
+        .get_keyring_account(&authority.pubkey())
+        .await
+        .expect("Failed to fetch keyring account");
+    assert_eq!(
+        keyring_account.data.len(),
+        get_instance_packed_len(&Keystore(vec![ml_kem_key.clone().into()])).unwrap()
+    );
+
+    // Check to make sure the key was added
+    let keystore = keyring
+        .get_keystore(&authority.pubkey())
+        .await
+        .expect("Failed to fetch keyring");
+    let mock_keystore = Keystore(vec![ml_kem_key.into()]);
     assert_eq!(keystore, mock_keystore);
 }
+
+#[tokio::test]
+async fn can_fund_rent_while_writing() {
+    let TestContext { keyring, authority } = TestContext::new().await;
+
+    // Create a keyring
+    keyring
+        .create_keyring(&authority)
+        .await
+        .expect("Failed to create keyring");
+
+    // The payer tops up the rent in the write itself
+    let curve_key = EncryptionKeyConfig::Curve25519(Curve25519(Pubkey::new_unique().to_bytes()));
+    let write = keyring
+        .add_entry(&authority, curve_key.clone())
+        .await
+        .expect("Failed to add key");
+    assert_eq!(write.outputs.len(), 1);
+    let keyring_account = keyring
+        .get_keyring_account(&authority.pubkey())
+        .await
+        .expect("Failed to fetch keyring account");
+    assert_eq!(
+        keyring_account.lamports,
+        Rent::default().minimum_balance(keyring_account.data.len())
+    );
+
+    // Chunked writes top up in their first chunk
+    let ml_kem_key = EncryptionKeyConfig::MlKem(
+        MlKem::new(
+            MlKemParameterSet::MlKem1024,
+            vec![7; MlKemParameterSet::MlKem1024.encapsulation_key_len()],
+        )
+        .expect("Invalid ML-KEM key"),
+    );
+    let write = keyring
+        .add_entry(&authority, ml_kem_key.clone())
+        .await
+        .expect("Failed to add key");
+    assert_eq!(write.outputs.len(), 2);
+    let keyring_account = keyring
+        .get_keyring_account(&authority.pubkey())
+        .await
+        .expect("Failed to fetch keyring account");
+    assert_eq!(
+        keyring_account.lamports,
+        Rent::default().minimum_balance(keyring_account.data.len())
+    );
+    assert_eq!(
+        keyring
+            .get_keystore(&authority.pubkey())
+            .await
+            .expect("Failed to fetch keyring"),
+        Keystore(vec![curve_key.into(), ml_kem_key.into()])
+    );
+}
+
+#[tokio::test]
+async fn can_repair_incomplete_chunked_write() {
+    let TestContext { keyring, authority } = TestContext::new().await;
+
+    // Create a keyring
+    keyring
+        .create_keyring(&authority)
+        .await
+        .expect("Failed to create keyring");
+
+    let ml_kem_key = EncryptionKeyConfig::MlKem(
+        MlKem::new(
+            MlKemParameterSet::MlKem1024,
+            vec![7; MlKemParameterSet::MlKem1024.encapsulation_key_len()],
+        )
+        .expect("Invalid ML-KEM key"),
+    );
+    let keystore = Keystore(vec![ml_kem_key.into()]);
+    let data = keystore.try_to_vec().unwrap();
+
+    // Fund rent for realloc
+    keyring
+        .fund_rent(&authority.pubkey(), data.len())
+        .await
+        .expect("Failed to fund rent");
+
+    // Only the first chunk of a write lands
+    keyring
+        .process_ixs(
+            &[spl_keyring_program::instruction::write_keyring(
+                &spl_keyring_program::id(),
+                &authority.pubkey(),
+                data.len() as u32,
+                0,
+                data[..data.len() / 2].to_vec(),
+            )
+            .unwrap()],
+            &[&authority],
+        )
+        .await
+        .expect("Failed to write chunk");
+    assert!(matches!(
+        keyring.get_keystore(&authority.pubkey()).await,
+        Err(KeyringError::InvalidKeystore)
+    ));
+
+    // Writing the intended keystore again repairs the keyring
+    keyring
+        .write_keystore(&authority, &keystore)
+        .await
+        .expect("Failed to write keystore");
+    assert_eq!(
+        keyring
+            .get_keystore(&authority.pubkey())
+            .await
+            .expect("Failed to fetch keyring"),
+        keystore
+    );
+}
+
+#[tokio::test]
+async fn fail_add_ml_kem_key_with_wrong_length() {
+    let TestContext { keyring, authority } = TestContext::new().await;
+
+    // Create a keyring
+    keyring
+        .create_keyring(&authority)
+        .await
+        .expect("Failed to create keyring");
+
+    // An ML-KEM-1024 sized key claiming to be ML-KEM-768
+    let ml_kem_key = EncryptionKeyConfig::MlKem(MlKem {
+        parameter_set: MlKemParameterSet::MlKem768,
+        key: vec![0; MlKemParameterSet::MlKem1024.encapsulation_key_len()],
+    });
+
+    let err = keyring.add_entry(&authority, ml_kem_key).await.unwrap_err();
+    assert!(matches!(
+        err,
+        KeyringError::InvalidKeyLength {
+            expected: 1184,
+            actual: 1568,
+        }
+    ));
+}
+
+#[tokio::test]
+async fn can_seal_to_keystore() {
+    let TestContext { keyring, authority } = TestContext::new().await;
+
+    let secret = StaticSecret::from(Pubkey::new_unique().to_bytes());
+    let curve_key =
+        EncryptionKeyConfig::Curve25519(Curve25519(PublicKey::from(&secret).to_bytes()));
+
+    // Create a keyring holding the key
+    create_keyring_with_entries(&keyring, &authority, [curve_key.into()]).await;
+
+    // Seal a message to the wallet and open it with the published key
+    let message = b"hello keyring";
+    let sealed = keyring
+        .seal(&authority.pubkey(), message)
+        .await
+        .expect("Failed to seal message");
+    let opened = sealed_box::open(&secret, &sealed).expect("Failed to open message");
+    assert_eq!(opened, message);
+}
+
+#[tokio::test]
+async fn can_seal_to_wallet_derived_key() {
+    let TestContext { keyring, authority } = TestContext::new().await;
+
+    // Create a keyring
+    keyring
+        .create_keyring(&authority)
+        .await
+        .expect("Failed to create keyring");
+
+    // Without any Curve25519 entry there is nothing to seal to
+    let err = keyring
+        .seal(&authority.pubkey(), b"hello keyring")
+        .await
+        .unwrap_err();
+    assert!(matches!(err, KeyringError::EntryNotFound));
+
+    let wallet_key = EncryptionKeyConfig::WalletDerivedCurve25519;
+
+    // Opt in to the wallet-derived key
+    keyring
+        .add_entry(&authority, wallet_key)
+        .await
+        .expect("Failed to add key");
+
+    // Seal a message to the wallet and open it with the wallet keypair
+    let message = b"hello keyring";
+    let sealed = keyring
+        .seal(&authority.pubkey(), message)
+        .await
+        .expect("Failed to seal message");
+    let secret = x25519::secret_key_from_wallet(&authority);
+    let opened = sealed_box::open(&secret, &sealed).expect("Failed to open message");
+    assert_eq!(opened, message);
+}
+
+#[tokio::test]
+async fn can_hpke_seal_to_keystore() {
+    let TestContext { keyring, authority } = TestContext::new().await;
+
+    let secret = StaticSecret::from(Pubkey::new_unique().to_bytes());
+    let config = HpkeConfig {
+        kem_id: hpke::KEM_X25519_HKDF_SHA256,
+        kdf_id: hpke::KDF_HKDF_SHA256,
+        aead_id: hpke::AEAD_CHACHA20_POLY1305,
+    };
+    let hpke_key = EncryptionKeyConfig::Hpke(Hpke {
+        public_key: PublicKey::from(&secret).as_bytes().to_vec(),
+        config,
+    });
+
+    // Create a keyring holding the key
+    create_keyring_with_entries(&keyring, &authority, [hpke_key.into()]).await;
+
+    // Seal a message with the advertised suite and open it
+    let message = b"hello keyring";
+    let (enc, ciphertext) = keyring
+        .hpke_seal(&authority.pubkey(), b"info", b"aad", message)
+        .await
+        .expect("Failed to seal message");
+    let opened = hpke::open(&config, &secret, &enc, b"info", b"aad", &ciphertext)
+        .expect("Failed to open message");
+    assert_eq!(opened, message);
+}
+
+#[tokio::test]
+async fn can_seal_envelope_to_multiple_keystores() {
+    let TestContext { keyring, authority } = TestContext::new().await;
+
+    // Fund a second wallet
+    let other = Keypair::new();
+    keyring
+        .process_ixs(
+            &[system_instruction::transfer(
+                &authority.pubkey(),
+                &other.pubkey(),
+                LAMPORTS_PER_SOL,
+            )],
+            &[&authority],
+        )
+        .await
+        .expect("Failed to fund wallet");
+
+    // The authority publishes a Curve25519 key
+    let secret = StaticSecret::from(Pubkey::new_unique().to_bytes());
+    let curve_key =
+        EncryptionKeyConfig::Curve25519(Curve25519(PublicKey::from(&secret).to_bytes()));
+
+    // The other wallet opts in to its wallet-derived key
+    let wallet_key = EncryptionKeyConfig::WalletDerivedCurve25519;
+
+    for (wallet, entry) in [
+        (&authority, curve_key.clone()),
+        (&other, wallet_key.clone()),
+    ] {
+        create_keyring_with_entries(&keyring, wallet, [entry.into()]).await;
+    }
+
+    // Seal one envelope for both wallets
+    let message = b"hello keyring";
+    let envelope: Envelope = keyring
+        .seal_envelope(&[authority.pubkey(), other.pubkey()], message)
+        .await
+        .expect("Failed to seal envelope");
+    assert_eq!(envelope.recipients.len(), 2);
+
+    // Headers follow the order of the recipients
+    let other_key = EncryptionKeyConfig::Curve25519(Curve25519(
+        x25519::public_key_from_wallet(&other.pubkey())
+            .unwrap()
+            .to_bytes(),
+    ));
+    assert_eq!(envelope.recipients[0].fingerprint, curve_key.fingerprint());
+    assert_eq!(envelope.recipients[1].fingerprint, other_key.fingerprint());
+    let reversed: Envelope = keyring
+        .seal_envelope(&[other.pubkey(), authority.pubkey()], message)
+        .await
+        .expect("Failed to seal envelope");
+    assert_eq!(reversed.recipients[0].fingerprint, other_key.fingerprint());
+    assert_eq!(reversed.recipients[1].fingerprint, curve_key.fingerprint());
+
+    // Both recipients can open it
+    assert_eq!(envelope.open(&secret).unwrap(), message);
+    assert_eq!(
+        envelope
+            .open(&x25519::secret_key_from_wallet(&other))
+            .unwrap(),
+        message
+    );
+
+    // A wallet without a keyring can't be a recipient
+    let missing = Pubkey::new_unique();
+    let err = keyring
+        .seal_envelope(&[authority.pubkey(), missing], message)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, KeyringError::KeystoreNotFound));
+
+    // Nor can one whose keyring is empty
+    keyring
+        .remove_entry(&other, wallet_key)
+        .await
+        .expect("Failed to remove key");
+    let err = keyring
+        .seal_envelope(&[other.pubkey(), missing], message)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, KeyringError::EntryNotFound));
+
+    // The first failing recipient decides the error
+    let err = keyring
+        .seal_envelope(&[missing, other.pubkey()], message)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, KeyringError::KeystoreNotFound));
+}
+
+#[tokio::test]
+async fn can_fetch_many_keystores() {
+    let TestContext { keyring, authority } = TestContext::new().await;
+
+    // Fund a second wallet
+    let corrupt = Keypair::new();
+    keyring
+        .process_ixs(
+            &[system_instruction::transfer(
+                &authority.pubkey(),
+                &corrupt.pubkey(),
+                LAMPORTS_PER_SOL,
+            )],
+            &[&authority],
+        )
+        .await
+        .expect("Failed to fund wallet");
+
+    // Create both keyrings
+    for wallet in [&authority, &corrupt] {
+        keyring
+            .create_keyring(wallet)
+            .await
+            .expect("Failed to create keyring");
+    }
+
+    let curve_key = EncryptionKeyConfig::Curve25519(Curve25519(Pubkey::new_unique().to_bytes()));
+
+    // Add an entry to the keystore
+    keyring
+        .add_entry(&authority, curve_key.clone())
+        .await
+        .expect("Failed to add key");
+
+    // Fill the second keyring with bytes that aren't a keystore
+    keyring
+        .fund_rent(&corrupt.pubkey(), 3)
+        .await
+        .expect("Failed to fund rent");
+    keyring
+        .process_ixs(
+            &[spl_keyring_program::instruction::update_keyring(
+                &spl_keyring_program::id(),
+                &corrupt.pubkey(),
+                vec![0xff; 3],
+            )
+            .unwrap()],
+            &[&corrupt],
+        )
+        .await
+        .expect("Failed to write keyring");
+
+    let missing = Pubkey::new_unique();
+    let mut keystores = keyring
+        .get_keystores(&[authority.pubkey(), corrupt.pubkey(), missing])
+        .await;
+    assert_eq!(keystores.len(), 3);
+    assert_eq!(
+        keystores.remove(&authority.pubkey()).unwrap().unwrap(),
+        Keystore(vec![curve_key.into()])
+    );
+    assert!(matches!(
+        keystores.remove(&corrupt.pubkey()).unwrap(),
+        Err(KeyringError::InvalidKeystore)
+    ));
+    assert!(matches!(
+        keystores.remove(&missing).unwrap(),
+        Err(KeyringError::KeystoreNotFound)
+    ));
+}
+
+#[tokio::test]
+async fn can_negotiate_key() {
+    let TestContext { keyring, authority } = TestContext::new().await;
+
+    let curve_key = EncryptionKeyConfig::Curve25519(Curve25519(Pubkey::new_unique().to_bytes()));
+
+    // Create a keyring holding the key
+    create_keyring_with_entries(&keyring, &authority, [curve_key.clone().into()]).await;
+
+    // The sender's first choice isn't published, so it falls back
+    let entry = keyring
+        .negotiate(
+            &authority.pubkey(),
+            &[KeyAlgorithm::Hpke, KeyAlgorithm::Curve25519],
+        )
+        .await
+        .expect("Failed to negotiate key");
+    assert_eq!(entry.key, curve_key);
+
+    // Nothing in common
+    let err = keyring
+        .negotiate(&authority.pubkey(), &[KeyAlgorithm::MlKem])
+        .await
+        .unwrap_err();
+    assert!(matches!(err, KeyringError::NoCompatibleKey));
+}
+
+#[tokio::test]
+async fn can_set_primary_key() {
+    let TestContext { keyring, authority } = TestContext::new().await;
+
+    let old_key = EncryptionKeyConfig::Curve25519(Curve25519(Pubkey::new_unique().to_bytes()));
+    let new_key = EncryptionKeyConfig::Curve25519(Curve25519(Pubkey::new_unique().to_bytes()));
+
+    // Create a keyring holding both keys
+    create_keyring_with_entries(
+        &keyring,
+        &authority,
+        [old_key.clone().into(), new_key.clone().into()],
+    )
+    .await;
+
+    // Mark the old key as primary, so it wins over the newer one
+    keyring
+        .set_primary(&authority, &old_key)
+        .await
+        .expect("Failed to set primary key");
+    let entry = keyring
+        .negotiate(&authority.pubkey(), &[KeyAlgorithm::Curve25519])
+        .await
+        .expect("Failed to negotiate key");
+    assert_eq!(entry.key, old_key);
+
+    // Rotate to the new key
+    keyring
+        .set_primary(&authority, &new_key)
+        .await
+        .expect("Failed to set primary key");
+    let keystore = keyring
+        .get_keystore(&authority.pubkey())
+        .await
+        .expect("Failed to fetch keyring");
+    let primaries: Vec<_> = keystore.0.iter().map(|e| e.config.primary).collect();
+    assert_eq!(primaries, [false, true]);
+
+    // Restrict the new key to signature verification
+    keyring
+        .set_usage(&authority, &new_key, KeyUsage::SIGNATURE_VERIFICATION)
+        .await
+        .expect("Failed to set key usage");
+    let entry = keyring
+        .negotiate(&authority.pubkey(), &[KeyAlgorithm::Curve25519])
+        .await
+        .expect("Failed to negotiate key");
+    assert_eq!(entry.key, old_key);
+}
+
+#[tokio::test]
+async fn can_add_key_with_metadata() {
+    let TestContext { keyring, authority } = TestContext::new().await;
+
+    // Create a keyring
+    keyring
+        .create_keyring(&authority)
+        .await
+        .expect("Failed to create keyring");
+
+    let curve_key = EncryptionKeyConfig::Curve25519(Curve25519(Pubkey::new_unique().to_bytes()));
+    let entry = KeystoreEntry {
+        key: curve_key.clone(),
+        config: EntryConfig {
+            metadata: EntryMetadata {
+                label: Some("Laptop key".to_string()),
+                device: Some("Laptop".to_string()),
+                created_at_slot: Some(1),
+                origin: Some("https://wallet.example".to_string()),
+            },
+            ..EntryConfig::default()
+        },
+    };
+
+    // Add an entry with metadata to the keystore
+    keyring
+        .add_entry(&authority, entry.clone())
+        .await
+        .expect("Failed to add key");
+
+    // Check to make sure the metadata round-trips
+    let keystore = keyring
+        .get_keystore(&authority.pubkey())
+        .await
+        .expect("Failed to fetch keyring");
+    assert_eq!(keystore, Keystore(vec![entry]));
+
+    // Relabel the key
+    let metadata = EntryMetadata {
+        label: Some("Old laptop key".to_string()),
+        ..keystore.0[0].config.metadata.clone()
+    };
+    keyring
+        .set_metadata(&authority, &curve_key, metadata.clone())
+        .await
+        .expect("Failed to set metadata");
+    let keystore = keyring
+        .get_keystore(&authority.pubkey())
+        .await
+        .expect("Failed to fetch keyring");
+    assert_eq!(keystore.0[0].config.metadata, metadata);
+}
+
+#[tokio::test]
+async fn can_rotate_key() {
+    let TestContext { keyring, authority } = TestContext::new().await;
+
+    let old_key = EncryptionKeyConfig::Curve25519(Curve25519(Pubkey::new_unique().to_bytes()));
+    let new_key = EncryptionKeyConfig::Curve25519(Curve25519(Pubkey::new_unique().to_bytes()));
+
+    // Create a keyring holding the old key as primary
+    let mut entry = KeystoreEntry::new(old_key.clone());
+    entry.config.primary = true;
+    create_keyring_with_entries(&keyring, &authority, [entry]).await;
+
+    // Rotate to the new key
+    keyring
+        .rotate_entry(&authority, &old_key, new_key.clone())
+        .await
+        .expect("Failed to rotate key");
+
+    // The old key is kept, retired
+    let keystore = keyring
+        .get_keystore(&authority.pubkey())
+        .await
+        .expect("Failed to fetch keyring");
+    assert_eq!(keystore.0.len(), 2);
+    assert_eq!(keystore.0[0].key, old_key);
+    assert_eq!(keystore.0[0].config.status, KeyStatus::Retired);
+    assert_eq!(keystore.0[1].key, new_key);
+    assert!(keystore.0[1].config.primary);
+
+    // Senders only see the new key
+    let entry = keyring
+        .negotiate(&authority.pubkey(), &[KeyAlgorithm::Curve25519])
+        .await
+        .expect("Failed to negotiate key");
+    assert_eq!(entry.key, new_key);
+}
+
+#[tokio::test]
+async fn can_rotate_large_keystore_atomically() {
+    let program_id = spl_keyring_program::id();
+    let ctx = program_test(program_id).start_with_context().await;
+    let ctx = Arc::new(Mutex::new(ctx));
+    let TestContext { keyring, authority } =
+        TestContext::from_context(Arc::clone(&ctx), program_id).await;
+    let keyring = keyring.with_simulator(Arc::new(BanksSimulator(Arc::clone(&ctx))));
+
+    let ml_kem_key = |byte| {
+        EncryptionKeyConfig::MlKem(
+            MlKem::new(
+                MlKemParameterSet::MlKem1024,
+                vec![byte; MlKemParameterSet::MlKem1024.encapsulation_key_len()],
+            )
+            .expect("Invalid ML-KEM key"),
+        )
+    };
+    let old_key = ml_kem_key(1);
+    let new_key = ml_kem_key(2);
+
+    // Create a keyring holding the old key as primary
+    let mut entry = KeystoreEntry::new(old_key.clone());
+    entry.config.primary = true;
+    create_keyring_with_entries(&keyring, &authority, [entry]).await;
+
+    // The rotated keystore is staged in chunks, then committed
+    let mut rotated = keyring
+        .get_keystore(&authority.pubkey())
+        .await
+        .expect("Failed to fetch keyring");
+    rotated
+        .rotate(&old_key, new_key.clone().into())
+        .expect("Failed to rotate key");
+    let len = rotated.try_to_vec().unwrap().len();
+    let transactions = len.div_ceil(MAX_STAGING_WRITE_LEN) + 1;
+    let report = keyring
+        .simulate_change(
+            &authority.pubkey(),
+            KeystoreChange::RotateEntry {
+                old: old_key.clone(),
+                new: new_key.clone().into(),
+            },
+        )
+        .await
+        .expect("Failed to simulate");
+    assert_eq!(report.err, None);
+    assert_eq!(report.transactions, transactions);
+    assert_eq!(report.len_after, len);
+
+    let write = keyring
+        .rotate_entry(&authority, &old_key, new_key.clone())
+        .await
+        .expect("Failed to rotate key");
+    assert_eq!(write.outputs.len(), transactions);
+    assert_eq!(
+        keyring
+            .get_keystore(&authority.pubkey())
+            .await
+            .expect("Failed to fetch keyring"),
+        rotated
+    );
+
+    // The commit closes the staging account, leaving the keyring just
+    // rent-exempt
+    let staging = KeyringStaging::pda(&program_id, &authority.pubkey()).0;
+    let staging_account = ctx
+        .lock()
+        .await
+        .banks_client
+        .get_account(staging)
+        .await
+        .expect("Failed to fetch staging account");
+    assert_eq!(staging_account, None);
+    let keyring_account = keyring
+        .get_keyring_account(&authority.pubkey())
+        .await
+        .expect("Failed to fetch keyring account");
+    assert_eq!(
+        keyring_account.lamports,
+        Rent::default().minimum_balance(len)
+    );
+
+    // The program checks staged keystores for duplicate keys on commit
+    let entry = KeystoreEntry::new(new_key);
+    let data = Keystore(vec![entry.clone(), entry]).try_to_vec().unwrap();
+    keyring
+        .process_ixs(
+            &[spl_keyring_program::instruction::write_staging(
+                &program_id,
+                &authority.pubkey(),
+                &authority.pubkey(),
+                data.len() as u32,
+                0,
+                data.clone(),
+            )
+            .unwrap()],
+            &[&authority],
+        )
+        .await
+        .expect("Failed to stage keystore");
+    let err = keyring
+        .process_ixs(
+            &[spl_keyring_program::instruction::commit_staging(
+                &program_id,
+                &authority.pubkey(),
+                &authority.pubkey(),
+            )
+            .unwrap()],
+            &[&authority],
+        )
+        .await
+        .expect_err("Duplicate keys should be rejected");
+    let KeyringError::Client(err) = err else {
+        panic!("Unexpected error: {err}");
+    };
+    assert_eq!(
+        err.downcast::<BanksClientError>().unwrap().unwrap(),
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(KeyringProgramError::DuplicateEntry as u32)
+        )
+    );
+    assert_eq!(
+        keyring
+            .get_keystore(&authority.pubkey())
+            .await
+            .expect("Failed to fetch keyring"),
+        rotated
+    );
+}
+
+#[tokio::test]
+async fn can_manage_entries_by_fingerprint() {
+    let TestContext { keyring, authority } = TestContext::new().await;
+
+    let curve_key = EncryptionKeyConfig::Curve25519(Curve25519(Pubkey::new_unique().to_bytes()));
+    let rsa_key = EncryptionKeyConfig::Rsa(Rsa([5; 64]));
+
+    // Create a keyring holding both keys
+    create_keyring_with_entries(
+        &keyring,
+        &authority,
+        [curve_key.clone().into(), rsa_key.clone().into()],
+    )
+    .await;
+
+    // Look up an entry by fingerprint
+    let entry = keyring
+        .get_entry_by_fingerprint(&authority.pubkey(), &rsa_key.fingerprint())
+        .await
+        .expect("Failed to fetch entry");
+    assert_eq!(entry.key, rsa_key);
+
+    // Revoke the Curve25519 key
+    keyring
+        .revoke_entry(&authority, &curve_key.fingerprint())
+        .await
+        .expect("Failed to revoke key");
+    let entry = keyring
+        .get_entry_by_fingerprint(&authority.pubkey(), &curve_key.fingerprint())
+        .await
+        .expect("Failed to fetch entry");
+    assert_eq!(entry.config.status, KeyStatus::Revoked);
+
+    // Remove the RSA key
+    keyring
+        .remove_entry_by_fingerprint(&authority, &rsa_key.fingerprint())
+        .await
+        .expect("Failed to remove key");
+    let keystore = keyring
+        .get_keystore(&authority.pubkey())
+        .await
+        .expect("Failed to fetch keyring");
+    assert_eq!(keystore.0.len(), 1);
+    assert!(matches!(
+        keyring
+            .get_entry_by_fingerprint(&authority.pubkey(), &rsa_key.fingerprint())
+            .await,
+        Err(KeyringError::EntryNotFound)
+    ));
+}
+
+#[tokio::test]
+async fn can_add_duplicate_key_idempotently() {
+    let TestContext { keyring, authority } = TestContext::new().await;
+
+    // Create a keyring
+    keyring
+        .create_keyring(&authority)
+        .await
+        .expect("Failed to create keyring");
+
+    let curve_key = EncryptionKeyConfig::Curve25519(Curve25519(Pubkey::new_unique().to_bytes()));
+    let entry = KeystoreEntry::new(curve_key.clone());
+
+    // Adding the same key twice leaves a single entry
+    for _ in 0..2 {
+        keyring
+            .add_entry(&authority, curve_key.clone())
+            .await
+            .expect("Failed to add key");
+    }
+    let keystore = keyring
+        .get_keystore(&authority.pubkey())
+        .await
+        .expect("Failed to fetch keyring");
+    assert_eq!(keystore, Keystore(vec![entry.clone()]));
+
+    // The program rejects a keystore holding the key twice, whether it's
+    // written at once or as the final chunk of a chunked write
+    let data = Keystore(vec![entry.clone(), entry]).try_to_vec().unwrap();
+    let program_id = spl_keyring_program::id();
+    for instruction in [
+        spl_keyring_program::instruction::update_keyring(
+            &program_id,
+            &authority.pubkey(),
+            data.clone(),
+        )
+        .unwrap(),
+        spl_keyring_program::instruction::write_keyring(
+            &program_id,
+            &authority.pubkey(),
+            data.len() as u32,
+            0,
+            data.clone(),
+        )
+        .unwrap(),
+    ] {
+        let err = keyring
+            .process_ixs(&[instruction], &[&authority])
+            .await
+            .expect_err("Duplicate keys should be rejected");
+        let KeyringError::Client(err) = err else {
+            panic!("Unexpected error: {err}");
+        };
+        assert_eq!(
+            err.downcast::<BanksClientError>().unwrap().unwrap(),
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(KeyringProgramError::DuplicateEntry as u32)
+            )
+        );
+    }
+}
+
+#[tokio::test]
+async fn can_use_custom_program_id() {
+    let program_id = Pubkey::new_unique();
+    let TestContext { keyring, authority } = TestContext::with_program_id(program_id).await;
+
+    // Create a keyring
+    keyring
+        .create_keyring(&authority)
+        .await
+        .expect("Failed to create keyring");
+
+    // The keyring lives at the custom program's address
+    let keyring_address = keyring.get_keyring_address(&authority.pubkey()).0;
+    assert_eq!(
+        keyring_address,
+        spl_keyring_program::state::Keyring::pda(&program_id, &authority.pubkey()).0
+    );
+    let keyring_account = keyring
+        .get_keyring_account(&authority.pubkey())
+        .await
+        .expect("Failed to fetch keyring account");
+    assert_eq!(keyring_account.owner, program_id);
+
+    let curve_key = EncryptionKeyConfig::Curve25519(Curve25519(Pubkey::new_unique().to_bytes()));
+
+    // Add and remove an entry through the custom program
+    keyring
+        .add_entry(&authority, curve_key.clone())
+        .await
+        .expect("Failed to add key");
+    let keystore = keyring
+        .get_keystore(&authority.pubkey())
+        .await
+        .expect("Failed to fetch keyring");
+    assert_eq!(keystore, Keystore(vec![curve_key.clone().into()]));
+
+    keyring
+        .remove_entry(&authority, curve_key)
+        .await
+        .expect("Failed to remove key");
+    let keystore = keyring
+        .get_keystore(&authority.pubkey())
+        .await
+        .expect("Failed to fetch keyring");
+    assert_eq!(keystore, Keystore::default());
+}
+
+#[tokio::test]
+async fn fail_get_keystore_with_wrong_owner() {
+    let program_id = Pubkey::new_unique();
+    let owner = Pubkey::new_unique();
+
+    // Plant an account owned by another program at the keyring address
+    let mut program_test = program_test(program_id);
+    program_test.add_account(
+        spl_keyring_program::state::Keyring::pda(&program_id, &owner).0,
+        Account {
+            lamports: LAMPORTS_PER_SOL,
+            data: Keystore::default().try_to_vec().unwrap(),
+            owner: Pubkey::new_unique(),
+            ..Account::default()
+        },
+    );
+    let TestContext { keyring, .. } = TestContext::start(program_test, program_id).await;
+
+    assert!(matches!(
+        keyring.get_keystore(&owner).await,
+        Err(KeyringError::IncorrectOwner)
+    ));
+}
+
+#[tokio::test]
+async fn can_manage_keyring_with_remote_signer() {
+    let TestContext { keyring, authority } = TestContext::new().await;
+
+    let device = Keypair::new();
+    let remote: Arc<dyn Signer> = Arc::new(RemoteSigner {
+        pubkey: device.pubkey(),
+        device,
+    });
+
+    // Fund the remote authority, which pays for its keyring account
+    keyring
+        .process_ixs(
+            &[system_instruction::transfer(
+                &authority.pubkey(),
+                &remote.pubkey(),
+                LAMPORTS_PER_SOL,
+            )],
+            &[&authority],
+        )
+        .await
+        .expect("Failed to fund authority");
+
+    // Create a keyring; the client's payer pays the fee
+    keyring
+        .create_keyring(remote.as_ref())
+        .await
+        .expect("Failed to create keyring");
+
+    let curve_key = EncryptionKeyConfig::Curve25519(Curve25519(Pubkey::new_unique().to_bytes()));
+
+    // Add an entry to the keystore
+    keyring
+        .add_entry(remote.as_ref(), curve_key.clone())
+        .await
+        .expect("Failed to add key");
+
+    let keystore = keyring
+        .get_keystore(&remote.pubkey())
+        .await
+        .expect("Failed to fetch keyring");
+    assert_eq!(keystore, Keystore(vec![curve_key.into()]));
+}
+
+#[tokio::test]
+async fn can_sign_offline_with_durable_nonce() {
+    let program_id = spl_keyring_program::id();
+    let ctx = program_test(program_id).start_with_context().await;
+    let ctx = Arc::new(Mutex::new(ctx));
+    let TestContext { keyring, authority } =
+        TestContext::from_context(Arc::clone(&ctx), program_id).await;
+
+    // Create a keyring
+    keyring
+        .create_keyring(&authority)
+        .await
+        .expect("Failed to create keyring");
+
+    // Create a durable nonce account controlled by the authority
+    let nonce_account = Keypair::new();
+    keyring
+        .process_ixs(
+            &system_instruction::create_nonce_account(
+                &authority.pubkey(),
+                &nonce_account.pubkey(),
+                &authority.pubkey(),
+                LAMPORTS_PER_SOL,
+            ),
+            &[&authority, &nonce_account],
+        )
+        .await
+        .expect("Failed to create nonce account");
+    let nonce = DurableNonce {
+        account: nonce_account.pubkey(),
+        authority: authority.pubkey(),
+    };
+
+    let curve_key = EncryptionKeyConfig::Curve25519(Curve25519(Pubkey::new_unique().to_bytes()));
+
+    // Build the unsigned transaction online
+    let mut keystore = keyring
+        .get_keystore(&authority.pubkey())
+        .await
+        .expect("Failed to fetch keyring");
+    keystore.add(curve_key.clone().into());
+    let mut transaction = keyring
+        .update_keystore_transaction(&authority.pubkey(), &keystore, Some(&nonce))
+        .await
+        .expect("Failed to build transaction");
+    assert!(!transaction.is_signed());
+
+    // Let the nonce's blockhash age, as it would during the offline round trip
+    ctx.lock().await.warp_to_slot(100).unwrap();
+
+    // Sign the message offline, rejecting signatures from the wrong key
+    let message = transaction.message_data();
+    assert!(matches!(
+        attach_signature(
+            &mut transaction,
+            &authority.pubkey(),
+            Keypair::new().sign_message(&message)
+        ),
+        Err(KeyringError::InvalidSignature)
+    ));
+    attach_signature(
+        &mut transaction,
+        &authority.pubkey(),
+        authority.sign_message(&message),
+    )
+    .expect("Failed to attach signature");
+
+    // Submit it
+    keyring
+        .send_signed_transaction(transaction)
+        .await
+        .expect("Failed to send transaction");
+    let keystore = keyring
+        .get_keystore(&authority.pubkey())
+        .await
+        .expect("Failed to fetch keyring");
+    assert_eq!(keystore, Keystore(vec![curve_key.into()]));
+}
+
+#[tokio::test]
+async fn can_compose_keystore_update() {
+    let TestContext { keyring, authority } = TestContext::new().await;
+
+    // Create a keyring
+    keyring
+        .create_keyring(&authority)
+        .await
+        .expect("Failed to create keyring");
+
+    let keyring_account = keyring
+        .get_keyring_account(&authority.pubkey())
+        .await
+        .expect("Failed to fetch keyring account");
+    let keystore = keyring
+        .get_keystore(&authority.pubkey())
+        .await
+        .expect("Failed to fetch keyring");
+    let curve_key = EncryptionKeyConfig::Curve25519(Curve25519(Pubkey::new_unique().to_bytes()));
+
+    // Build the change, rent top-up included, without touching the chain
+    let update = instruction::add_entry(
+        &spl_keyring_program::id(),
+        &authority.pubkey(),
+        &authority.pubkey(),
+        keyring_account.lamports,
+        &Rent::default(),
+        &keystore,
+        curve_key.clone(),
+    )
+    .expect("Failed to build update");
+
+    // Send it alongside an unrelated instruction, under a limit covering both
+    let recipient = Pubkey::new_unique();
+    let mut instructions = vec![ComputeBudgetInstruction::set_compute_unit_limit(
+        update.compute_units + 1_000,
+    )];
+    instructions.extend(update.instructions);
+    instructions.push(system_instruction::transfer(
+        &authority.pubkey(),
+        &recipient,
+        LAMPORTS_PER_SOL,
+    ));
+    keyring
+        .process_ixs(&instructions, &[&authority])
+        .await
+        .expect("Failed to process update");
+
+    let keyring_account = keyring
+        .get_keyring_account(&authority.pubkey())
+        .await
+        .expect("Failed to fetch keyring account");
+    assert_eq!(keyring_account.data.len(), update.new_len);
+    assert!(Rent::default().is_exempt(keyring_account.lamports, update.new_len));
+    let keystore = keyring
+        .get_keystore(&authority.pubkey())
+        .await
+        .expect("Failed to fetch keyring");
+    assert_eq!(keystore, Keystore(vec![curve_key.into()]));
+}
+
+#[tokio::test]
+async fn can_set_compute_budget() {
+    let program_id = spl_keyring_program::id();
+    let ctx = program_test(program_id).start_with_context().await;
+    let ctx = Arc::new(Mutex::new(ctx));
+    let TestContext { keyring, authority } =
+        TestContext::from_context(Arc::clone(&ctx), program_id).await;
+
+    // Every transaction requests a fixed limit and pays a priority fee
+    let keyring = keyring
+        .with_compute_unit_limit(ComputeUnitLimit::Static(100_000))
+        .with_compute_unit_price(1_000);
+    let transaction = keyring
+        .create_keyring_transaction(&authority.pubkey(), None)
+        .await
+        .expect("Failed to build transaction");
+    let budget_instructions = transaction
+        .message
+        .instructions
+        .iter()
+        .take_while(|ix| {
+            transaction.message.account_keys[ix.program_id_index as usize]
+                == solana_sdk::compute_budget::id()
+        })
+        .count();
+    assert_eq!(budget_instructions, 2);
+
+    // Create a keyring
+    keyring
+        .create_keyring(&authority)
+        .await
+        .expect("Failed to create keyring");
+
+    // Estimate the limit by simulation instead
+    let keyring = keyring
+        .with_compute_unit_limit(ComputeUnitLimit::Simulated)
+        .with_simulator(Arc::new(BanksSimulator(Arc::clone(&ctx))));
+
+    let curve_key = EncryptionKeyConfig::Curve25519(Curve25519(Pubkey::new_unique().to_bytes()));
+
+    // Add an entry to the keystore
+    keyring
+        .add_entry(&authority, curve_key.clone())
+        .await
+        .expect("Failed to add key");
+
+    let keystore = keyring
+        .get_keystore(&authority.pubkey())
+        .await
+        .expect("Failed to fetch keyring");
+    assert_eq!(keystore, Keystore(vec![curve_key.into()]));
+}
+
+#[tokio::test]
+async fn can_price_composed_keystore_update() {
+    let TestContext { keyring, authority } = TestContext::new().await;
+    let keyring = keyring
+        .with_compute_unit_limit(ComputeUnitLimit::Static(100_000))
+        .with_compute_unit_price(1_000);
+
+    // Create a keyring
+    keyring
+        .create_keyring(&authority)
+        .await
+        .expect("Failed to create keyring");
+
+    let keyring_account = keyring
+        .get_keyring_account(&authority.pubkey())
+        .await
+        .expect("Failed to fetch keyring account");
+    let curve_key = EncryptionKeyConfig::Curve25519(Curve25519(Pubkey::new_unique().to_bytes()));
+    let update = instruction::add_entry(
+        &spl_keyring_program::id(),
+        &authority.pubkey(),
+        &authority.pubkey(),
+        keyring_account.lamports,
+        &Rent::default(),
+        &Keystore::default(),
+        curve_key.clone(),
+    )
+    .expect("Failed to build update");
+
+    // The caller sets the limit, the client still adds its price
+    let mut instructions = vec![ComputeBudgetInstruction::set_compute_unit_limit(
+        update.compute_units,
+    )];
+    instructions.extend(update.instructions);
+    let transaction = keyring
+        .build_transaction(&instructions, None)
+        .await
+        .expect("Failed to build transaction");
+    let budget = transaction
+        .message
+        .instructions
+        .iter()
+        .filter(|ix| {
+            transaction.message.account_keys[ix.program_id_index as usize]
+                == solana_sdk::compute_budget::id()
+        })
+        .map(|ix| ComputeBudgetInstruction::try_from_slice(&ix.data).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        budget,
+        vec![
+            ComputeBudgetInstruction::SetComputeUnitPrice(1_000),
+            ComputeBudgetInstruction::SetComputeUnitLimit(update.compute_units),
+        ]
+    );
+
+    keyring
+        .process_ixs(&instructions, &[&authority])
+        .await
+        .expect("Failed to process update");
+    let keystore = keyring
+        .get_keystore(&authority.pubkey())
+        .await
+        .expect("Failed to fetch keyring");
+    assert_eq!(keystore, Keystore(vec![curve_key.into()]));
+}
+
+#[tokio::test]
+async fn can_refetch_keystore_after_write() {
+    let TestContext { keyring, authority } = TestContext::new().await;
+    let keyring = keyring.with_refetch_after_write(true);
+
+    // Create a keyring
+    keyring
+        .create_keyring(&authority)
+        .await
+        .expect("Failed to create keyring");
+
+    let curve_key = EncryptionKeyConfig::Curve25519(Curve25519(Pubkey::new_unique().to_bytes()));
+
+    // The write reports one transaction and the keystore after it
+    let write = keyring
+        .add_entry(&authority, curve_key.clone())
+        .await
+        .expect("Failed to add key");
+    assert_eq!(write.outputs.len(), 1);
+    assert_eq!(
+        write.keystore,
+        Some(Keystore(vec![curve_key.clone().into()]))
+    );
+
+    // Adding it again sends nothing
+    let write = keyring
+        .add_entry(&authority, curve_key.clone())
+        .await
+        .expect("Failed to add key");
+    assert!(write.outputs.is_empty());
+    assert_eq!(write.keystore, Some(Keystore(vec![curve_key.into()])));
+}
+
+#[tokio::test]
+async fn can_send_versioned_transactions() {
+    let program_id = spl_keyring_program::id();
+    let ctx = program_test(program_id).start_with_context().await;
+    let ctx = Arc::new(Mutex::new(ctx));
+    let TestContext { keyring, authority } =
+        TestContext::from_context(Arc::clone(&ctx), program_id).await;
+    let keyring =
+        keyring.with_versioned_transactions(Arc::new(BanksSender(Arc::clone(&ctx))), vec![]);
+
+    let curve_key = EncryptionKeyConfig::Curve25519(Curve25519(Pubkey::new_unique().to_bytes()));
+
+    // Create a keyring holding the key
+    create_keyring_with_entries(&keyring, &authority, [curve_key.clone().into()]).await;
+
+    let keystore = keyring
+        .get_keystore(&authority.pubkey())
+        .await
+        .expect("Failed to fetch keyring");
+    assert_eq!(keystore, Keystore(vec![curve_key.into()]));
+
+    // Put the keyring address in an on-chain lookup table
+    let keyring_address = keyring.get_keyring_address(&authority.pubkey()).0;
+    let lookup_table = Pubkey::new_unique();
+    {
+        let mut ctx = ctx.lock().await;
+        ctx.set_account(
+            &lookup_table,
+            &lookup_table_account(&authority.pubkey(), &[keyring_address]).into(),
+        );
+        // Addresses become usable the slot after they are added
+        ctx.warp_to_slot(100).unwrap();
+    }
+    let keyring = keyring.with_versioned_transactions(
+        Arc::new(BanksSender(Arc::clone(&ctx))),
+        vec![AddressLookupTableAccount {
+            key: lookup_table,
+            addresses: vec![keyring_address],
+        }],
+    );
+
+    // Accounts in a lookup table are loaded from it rather than listed
+    let update = |keystore: &Keystore| {
+        spl_keyring_program::instruction::update_keyring(
+            &program_id,
+            &authority.pubkey(),
+            keystore.try_to_vec().unwrap(),
+        )
+        .unwrap()
+    };
+    let transaction = keyring
+        .build_versioned_transaction(&[update(&Keystore::default())], None)
+        .await
+        .expect("Failed to build transaction");
+    assert!(!transaction
+        .message
+        .static_account_keys()
+        .contains(&keyring_address));
+    assert_eq!(
+        transaction.message.address_table_lookups().map(<[_]>::len),
+        Some(1)
+    );
+
+    // The runtime resolves the keyring through the table
+    keyring
+        .process_ixs(&[update(&Keystore::default())], &[&authority])
+        .await
+        .expect("Failed to process update");
+    let keystore = keyring
+        .get_keystore(&authority.pubkey())
+        .await
+        .expect("Failed to fetch keyring");
+    assert_eq!(keystore, Keystore::default());
+}
+
+#[tokio::test]
+async fn can_write_chunked_keystore_through_lookup_table() {
+    let program_id = spl_keyring_program::id();
+    let ctx = program_test(program_id).start_with_context().await;
+    let ctx = Arc::new(Mutex::new(ctx));
+    let TestContext { keyring, authority } =
+        TestContext::from_context(Arc::clone(&ctx), program_id).await;
+
+    // Create a keyring
+    keyring
+        .create_keyring(&authority)
+        .await
+        .expect("Failed to create keyring");
+
+    // A payer other than the authority, so every write carries two
+    // signatures
+    let payer = Keypair::new();
+    keyring
+        .process_ixs(
+            &[system_instruction::transfer(
+                &authority.pubkey(),
+                &payer.pubkey(),
+                LAMPORTS_PER_SOL,
+            )],
+            &[&authority],
+        )
+        .await
+        .expect("Failed to fund payer");
+
+    // Put the keyring address in an on-chain lookup table
+    let keyring_address = keyring.get_keyring_address(&authority.pubkey()).0;
+    let lookup_table = Pubkey::new_unique();
+    {
+        let mut ctx = ctx.lock().await;
+        ctx.set_account(
+            &lookup_table,
+            &lookup_table_account(&authority.pubkey(), &[keyring_address]).into(),
+        );
+        ctx.warp_to_slot(100).unwrap();
+    }
+
+    // Both compute budget instructions and the lookup make each chunk's
+    // transaction as large as a write gets
+    let keyring = Keyring::new(
+        Arc::new(ProgramBanksClient::new_from_context(
+            Arc::clone(&ctx),
+            ProgramBanksClientProcessTransaction,
+        )),
+        &program_id,
+        Arc::new(payer),
+    )
+    .with_compute_unit_price(1)
+    .with_compute_unit_limit(ComputeUnitLimit::Static(100_000))
+    .with_versioned_transactions(
+        Arc::new(BanksSender(Arc::clone(&ctx))),
+        vec![AddressLookupTableAccount {
+            key: lookup_table,
+            addresses: vec![keyring_address],
+        }],
+    );
+
+    let ml_kem_key = EncryptionKeyConfig::MlKem(
+        MlKem::new(
+            MlKemParameterSet::MlKem1024,
+            vec![7; MlKemParameterSet::MlKem1024.encapsulation_key_len()],
+        )
+        .expect("Invalid ML-KEM key"),
+    );
+    let keystore = Keystore(vec![ml_kem_key.clone().into()]);
+
+    // Every chunk fits in a packet
+    let write = keyring
+        .add_entry(&authority, ml_kem_key)
+        .await
+        .expect("Failed to add key");
+    assert_eq!(write.outputs.len(), 2);
+    assert_eq!(
+        keyring
+            .get_keystore(&authority.pubkey())
+            .await
+            .expect("Failed to fetch keyring"),
+        keystore
+    );
+}
+
+/// An active address lookup table holding `addresses`, as the address lookup
+/// table program lays it out
+fn lookup_table_account(authority: &Pubkey, addresses: &[Pubkey]) -> Account {
+    // `ProgramState::LookupTable`, never deactivated, last extended in the
+    // first slot, then the authority and padding up to the 56-byte metadata
+    let mut data = 1u32.to_le_bytes().to_vec();
+    data.extend_from_slice(&u64::MAX.to_le_bytes());
+    data.extend_from_slice(&0u64.to_le_bytes());
+    data.push(0);
+    data.push(1);
+    data.extend_from_slice(authority.as_ref());
+    data.extend_from_slice(&[0; 2]);
+    for address in addresses {
+        data.extend_from_slice(address.as_ref());
+    }
+    Account {
+        lamports: Rent::default().minimum_balance(data.len()),
+        data,
+        owner: address_lookup_table_program::id(),
+        executable: false,
+        rent_epoch: 0,
+    }
+}
+
+mod address_lookup_table_program {
+    solana_sdk::declare_id!("AddressLookupTab1e1111111111111111111111111");
+}
+
+#[tokio::test]
+async fn can_simulate_keyring_operations() {
+    let program_id = spl_keyring_program::id();
+    let ctx = program_test(program_id).start_with_context().await;
+    let ctx = Arc::new(Mutex::new(ctx));
+    let TestContext { keyring, authority } =
+        TestContext::from_context(Arc::clone(&ctx), program_id).await;
+    let curve_key = EncryptionKeyConfig::Curve25519(Curve25519(Pubkey::new_unique().to_bytes()));
+
+    // Dry runs need a simulator
+    assert!(matches!(
+        keyring.simulate_create_keyring(&authority.pubkey()).await,
+        Err(KeyringError::SimulatorNotConfigured)
+    ));
+    let keyring = keyring.with_simulator(Arc::new(BanksSimulator(Arc::clone(&ctx))));
+
+    // Creating a keyring funds an empty account
+    let report = keyring
+        .simulate_create_keyring(&authority.pubkey())
+        .await
+        .expect("Failed to simulate");
+    assert_eq!(report.err, None);
+    assert_eq!(report.transactions, 1);
+    assert_eq!(report.len_after, 0);
+    assert_eq!(report.rent, Rent::default().minimum_balance(0));
+    assert!(report.fee >= LAMPORTS_PER_SIGNATURE);
+    assert_eq!(report.lamports_required(), report.fee + report.rent);
+    assert!(report.units_consumed > 0);
+    assert!(!report.logs.is_empty());
+
+    // Create a keyring
+    keyring
+        .create_keyring(&authority)
+        .await
+        .expect("Failed to create keyring");
+
+    // Adding a key grows the keyring, which needs more rent
+    let new_len = Keystore(vec![curve_key.clone().into()])
+        .try_to_vec()
+        .unwrap()
+        .len();
+    let report = keyring
+        .simulate_change(
+            &authority.pubkey(),
+            KeystoreChange::AddEntry(curve_key.clone().into()),
+        )
+        .await
+        .expect("Failed to simulate");
+    assert_eq!(report.len_before, 0);
+    assert_eq!(report.len_after, new_len);
+    assert_eq!(
+        report.rent,
+        Rent::default().minimum_balance(new_len) - Rent::default().minimum_balance(0)
+    );
+
+    // Fund rent for realloc
+    keyring
+        .process_ixs(
+            &[system_instruction::transfer(
+                &authority.pubkey(),
+                &keyring.get_keyring_address(&authority.pubkey()).0,
+                report.rent,
+            )],
+            &[&authority],
+        )
+        .await
+        .expect("Failed to fund rent");
+
+    let report = keyring
+        .simulate_change(
+            &authority.pubkey(),
+            KeystoreChange::AddEntry(curve_key.clone().into()),
+        )
+        .await
+        .expect("Failed to simulate");
+    assert_eq!(report.err, None);
+    assert_eq!(report.rent, 0);
+    assert!(report.units_consumed > 0);
+
+    // Nothing was written
+    let keystore = keyring
+        .get_keystore(&authority.pubkey())
+        .await
+        .expect("Failed to fetch keyring");
+    assert_eq!(keystore, Keystore::default());
+
+    // Add an entry to the keystore
+    keyring
+        .add_entry(&authority, curve_key.clone())
+        .await
+        .expect("Failed to add key");
+
+    // Adding it again is a no-op
+    let report = keyring
+        .simulate_change(
+            &authority.pubkey(),
+            KeystoreChange::AddEntry(curve_key.clone().into()),
+        )
+        .await
+        .expect("Failed to simulate");
+    assert_eq!(report.transactions, 0);
+    assert_eq!(report.len_after, new_len);
+    assert_eq!(report.lamports_required(), 0);
+
+    // Client-side errors come back as they would from the operation
+    let missing = EncryptionKeyConfig::Curve25519(Curve25519(Pubkey::new_unique().to_bytes()));
+    assert!(matches!(
+        keyring
+            .simulate_change(&authority.pubkey(), KeystoreChange::RemoveEntry(missing))
+            .await,
+        Err(KeyringError::EntryNotFound)
+    ));
+
+    // Removing the key shrinks the keyring
+    let report = keyring
+        .simulate_change(&authority.pubkey(), KeystoreChange::RemoveEntry(curve_key))
+        .await
+        .expect("Failed to simulate");
+    assert_eq!(report.err, None);
+    assert_eq!(report.len_before, new_len);
+    assert!(report.len_after < new_len);
+    assert_eq!(report.rent, 0);
+
+    // A builder update runs as one transaction, rent top-up included
+    let keyring_account = keyring
+        .get_keyring_account(&authority.pubkey())
+        .await
+        .expect("Failed to fetch keyring account");
+    let keystore = keyring
+        .get_keystore(&authority.pubkey())
+        .await
+        .expect("Failed to fetch keyring");
+    let rsa_key = EncryptionKeyConfig::Rsa(Rsa([5; 64]));
+    let update = instruction::add_entry(
+        &spl_keyring_program::id(),
+        &authority.pubkey(),
+        &authority.pubkey(),
+        keyring_account.lamports,
+        &Rent::default(),
+        &keystore,
+        rsa_key,
+    )
+    .expect("Failed to build update");
+    assert_eq!(update.instructions.len(), 2);
+    let report = keyring
+        .simulate_keystore_update(&authority.pubkey(), &update)
+        .await
+        .expect("Failed to simulate");
+    assert_eq!(report.err, None);
+    assert_eq!(report.transactions, 1);
+    assert_eq!(report.len_after, update.new_len);
+    assert_eq!(
+        report.rent,
+        Rent::default().minimum_balance(update.new_len) - keyring_account.lamports
+    );
+    assert!(report.units_consumed > 0);
+
+    // Closing the keyring frees the account
+    let destination = Pubkey::new_unique();
+    let report = keyring
+        .simulate_close_keyring(&authority.pubkey(), &destination)
+        .await
+        .expect("Failed to simulate");
+    assert_eq!(report.err, None);
+    assert_eq!(report.transactions, 1);
+    assert_eq!(report.len_before, keyring_account.data.len());
+    assert_eq!(report.len_after, 0);
+    assert_eq!(report.rent, 0);
+    assert!(keyring
+        .get_keyring_account(&authority.pubkey())
+        .await
+        .is_ok());
+}
+
+#[tokio::test]
+async fn can_query_keyring_without_payer() {
+    let program_id = spl_keyring_program::id();
+    let ctx = program_test(program_id).start_with_context().await;
+    let ctx = Arc::new(Mutex::new(ctx));
+    let TestContext { keyring, authority } =
+        TestContext::from_context(Arc::clone(&ctx), program_id).await;
+
+    let curve_key = EncryptionKeyConfig::Curve25519(Curve25519(Pubkey::new_unique().to_bytes()));
+    let rsa_key = EncryptionKeyConfig::Rsa(Rsa([5; 64]));
+
+    // Create a keyring holding both keys
+    create_keyring_with_entries(
+        &keyring,
+        &authority,
+        [curve_key.clone().into(), rsa_key.clone().into()],
+    )
+    .await;
+
+    // A reader needs only a client
+    let reader = KeyringReader::new(
+        Arc::new(ProgramBanksClient::new_from_context(
+            Arc::clone(&ctx),
+            ProgramBanksClientProcessTransaction,
+        )),
+        &program_id,
+    );
+    assert_eq!(
+        reader.get_keyring_address(&authority.pubkey()),
+        keyring.get_keyring_address(&authority.pubkey())
+    );
+
+    let keystore = reader
+        .get_keystore(&authority.pubkey())
+        .await
+        .expect("Failed to fetch keyring");
+    assert_eq!(
+        keystore,
+        Keystore(vec![curve_key.clone().into(), rsa_key.clone().into()])
+    );
+
+    // Query by algorithm
+    let entries = reader
+        .get_entries_by_algorithm(&authority.pubkey(), KeyAlgorithm::Rsa)
+        .await
+        .expect("Failed to fetch entries");
+    assert_eq!(entries, vec![rsa_key.clone().into()]);
+    let entries = reader
+        .get_entries_by_algorithm(&authority.pubkey(), KeyAlgorithm::MlKem)
+        .await
+        .expect("Failed to fetch entries");
+    assert!(entries.is_empty());
+
+    // Query by fingerprint
+    let entry = reader
+        .get_entry_by_fingerprint(&authority.pubkey(), &curve_key.fingerprint())
+        .await
+        .expect("Failed to fetch entry");
+    assert_eq!(entry.key, curve_key);
+
+    // Query in batch
+    let missing = Pubkey::new_unique();
+    let keystores = reader.get_keystores(&[authority.pubkey(), missing]).await;
+    assert_eq!(keystores[&authority.pubkey()].as_ref().unwrap(), &keystore);
+    assert!(matches!(
+        keystores[&missing],
+        Err(KeyringError::KeystoreNotFound)
+    ));
+
+    // The writer's reader sees the same keystore
+    let keystore_from_writer = keyring
+        .reader()
+        .get_keystore(&authority.pubkey())
+        .await
+        .expect("Failed to fetch keyring");
+    assert_eq!(keystore_from_writer, keystore);
+}
+
+#[tokio::test]
+async fn can_close_keyring() {
+    let program_id = spl_keyring_program::id();
+    let ctx = program_test(program_id).start_with_context().await;
+    let ctx = Arc::new(Mutex::new(ctx));
+    let TestContext { keyring, authority } =
+        TestContext::from_context(Arc::clone(&ctx), program_id).await;
+    let destination = Pubkey::new_unique();
+
+    // Closing a missing keyring sends nothing
+    assert!(matches!(
+        keyring.close_keyring(&authority, &destination).await,
+        Err(KeyringError::KeystoreNotFound)
+    ));
+
+    // Create a keyring
+    keyring
+        .create_keyring(&authority)
+        .await
+        .expect("Failed to create keyring");
+    let lamports = keyring
+        .get_keyring_account(&authority.pubkey())
+        .await
+        .expect("Failed to fetch keyring")
+        .lamports;
+
+    // Close it, returning its lamports
+    keyring
+        .close_keyring(&authority, &destination)
+        .await
+        .expect("Failed to close keyring");
+    assert!(matches!(
+        keyring.get_keystore(&authority.pubkey()).await,
+        Err(KeyringError::KeystoreNotFound)
+    ));
+    let destination_account = ctx
+        .lock()
+        .await
+        .banks_client
+        .get_account(destination)
+        .await
+        .expect("Failed to fetch destination")
+        .expect("Destination not funded");
+    assert_eq!(destination_account.lamports, lamports);
+
+    // The keyring can be created again
+    keyring
+        .create_keyring(&authority)
+        .await
+        .expect("Failed to create keyring");
+    let keystore = keyring
+        .get_keystore(&authority.pubkey())
+        .await
+        .expect("Failed to fetch keyring");
+    assert_eq!(keystore, Keystore::default());
+}
+
+#[test]
+fn can_manage_keyring_without_runtime() {
+    let runtime = tokio::runtime::Runtime::new().expect("Failed to start runtime");
+    let program_id = spl_keyring_program::id();
+    let ctx = runtime.block_on(program_test(program_id).start_with_context());
+    let authority = keypair_clone(&ctx.payer);
+    let ctx = Arc::new(Mutex::new(ctx));
+
+    // Every call below blocks the test thread, outside of the runtime
+    let keyring = BlockingKeyring::new(
+        Arc::new(BlockingBanksClient {
+            runtime: runtime.handle().clone(),
+            client: ProgramBanksClient::new_from_context(
+                Arc::clone(&ctx),
+                ProgramBanksClientProcessTransaction,
+            ),
+        }),
+        &program_id,
+        Arc::new(keypair_clone(&authority)),
+    );
+    let curve_key = EncryptionKeyConfig::Curve25519(Curve25519(Pubkey::new_unique().to_bytes()));
+
+    // Create a keyring
+    keyring
+        .create_keyring(&authority)
+        .expect("Failed to create keyring");
+
+    // Errors are the same as the async client's
+    assert!(matches!(
+        keyring.remove_entry(&authority, curve_key.clone()),
+        Err(KeyringError::EntryNotFound)
+    ));
+
+    // Fund rent for realloc
+    let new_len = Keystore(vec![curve_key.clone().into()])
+        .try_to_vec()
+        .unwrap()
+        .len();
+    assert!(keyring
+        .fund_rent(&authority.pubkey(), new_len)
+        .expect("Failed to fund rent")
+        .is_some());
+    assert!(keyring
+        .fund_rent(&authority.pubkey(), new_len)
+        .expect("Failed to fund rent")
+        .is_none());
+
+    // Add an entry to the keystore
+    keyring
+        .add_entry(&authority, curve_key.clone())
+        .expect("Failed to add key");
+    let keystore = keyring
+        .get_keystore(&authority.pubkey())
+        .expect("Failed to fetch keyring");
+    assert_eq!(keystore, Keystore(vec![curve_key.clone().into()]));
+    let keystores = keyring.get_keystores(&[authority.pubkey()]);
+    assert_eq!(keystores[&authority.pubkey()].as_ref().unwrap(), &keystore);
+
+    // Remove it
+    keyring
+        .remove_entry(&authority, curve_key)
+        .expect("Failed to remove key");
+    let keystore = keyring
+        .get_keystore(&authority.pubkey())
+        .expect("Failed to fetch keyring");
+    assert_eq!(keystore, Keystore::default());
+
+    // Close the keyring
+    keyring
+        .close_keyring(&authority, &authority.pubkey())
+        .expect("Failed to close keyring");
+    assert!(matches!(
+        keyring.get_keystore(&authority.pubkey()),
+        Err(KeyringError::KeystoreNotFound)
+    ));
+}
@@ -8,7 +8,7 @@ import {
   TransactionMessage,
   VersionedTransaction,
 } from "@solana/web3.js";
-import { EncryptionKeyConfig, Keystore } from "./keystore";
+import { EncryptionKeyConfig, Keystore, KeystoreEntry } from "./keystore";
 import { seq, struct, u8 } from "@solana/buffer-layout";
 
 /**
@@ -161,18 +161,25 @@ export class Keyring {
   /**
    * Create an `UpdateKeyring` instruction for adding an entry.
    * Automatically creates the necessary instruction data for adding a new key entry.
+   * A bare key gets the default configuration. If the keystore already holds
+   * the key, the instruction rewrites it unchanged.
    * @param authority The user authority
-   * @param entry The new key to add
+   * @param entry The new key or entry to add
    * @returns An `UpdateKeyring` instruction
    */
   async addEntryInstruction(
     authority: PublicKey,
-    entry: EncryptionKeyConfig,
+    entry: EncryptionKeyConfig | KeystoreEntry,
   ): Promise<TransactionInstruction> {
     const [keyringAddress] = this.getKeyringAddress(authority);
     const keystore = await this.getKeystore(authority);
 
-    keystore.entries.push(entry);
+    const newEntry =
+      entry instanceof KeystoreEntry ? entry : new KeystoreEntry(entry);
+    const newKey = newEntry.key.serialize();
+    if (!keystore.entries.some((e) => e.key.serialize().equals(newKey))) {
+      keystore.entries.push(newEntry);
+    }
 
     const newKeyringData = keystore.serialize();
     const newKeyringDataLength = newKeyringData.length;
@@ -203,20 +210,19 @@ export class Keyring {
    * Create an `UpdateKeyring` instruction for removing an entry.
    * Automatically creates the necessary instruction data for removing a new key entry.
    * @param authority The user authority
-   * @param entry The key to remove
+   * @param key The key to remove
    * @returns An `UpdateKeyring` instruction
    */
   async removeEntryInstruction(
     authority: PublicKey,
-    entry: EncryptionKeyConfig,
+    key: EncryptionKeyConfig,
   ): Promise<TransactionInstruction> {
     const [keyringAddress] = this.getKeyringAddress(authority);
     const keystore = await this.getKeystore(authority);
 
+    const removed = key.serialize();
     const newKeystore = new Keystore(
-      keystore.entries.filter(
-        (e) => JSON.stringify(e) !== JSON.stringify(entry),
-      ),
+      keystore.entries.filter((e) => !e.key.serialize().equals(removed)),
     );
 
     const newKeyringData = newKeystore.serialize();
@@ -1,9 +1,20 @@
 /**
  * A keystore
+ *
+ * Serialized as `Keystore.VERSION_MARKER`, the layout version, then a `u32`
+ * entry count and the entries, each a length-prefixed key followed by a
+ * length-prefixed configuration. Keystores written before the layout was
+ * versioned are a bare list of keys; they still deserialize, each key
+ * getting the default configuration.
  */
 export class Keystore {
-  entries: EncryptionKeyConfig[];
-  constructor(entries: EncryptionKeyConfig[]) {
+  // First byte of a versioned keystore
+  static VERSION_MARKER = 0xff;
+  // Current layout version
+  static VERSION = 1;
+
+  entries: KeystoreEntry[];
+  constructor(entries: KeystoreEntry[]) {
     this.entries = entries;
   }
 
@@ -14,7 +25,7 @@ export class Keystore {
   packedLength(): number {
     return this.entries.reduce(
       (sum, entry) => sum + entry.getPackedLength(),
-      0,
+      2 + 4,
     );
   }
 
@@ -23,15 +34,14 @@ export class Keystore {
    * @returns A buffer containing the serialized keystore
    */
   serialize(): Buffer {
-    const buf = Buffer.alloc(this.packedLength() + 4);
-    buf.writeUInt32LE(this.entries.length, 0);
-    let offset = 4;
-    for (const entry of this.entries) {
-      const entryBuf = entry.serialize();
-      entryBuf.copy(buf, offset);
-      offset += entryBuf.length;
-    }
-    return buf;
+    const header = Buffer.alloc(2 + 4);
+    header[0] = Keystore.VERSION_MARKER;
+    header[1] = Keystore.VERSION;
+    header.writeUInt32LE(this.entries.length, 2);
+    return Buffer.concat([
+      header,
+      ...this.entries.map((entry) => entry.serialize()),
+    ]);
   }
 
   /**
@@ -43,20 +53,261 @@ export class Keystore {
     if (buf.length === 0) {
       return new Keystore([]);
     }
+    if (buf[0] !== Keystore.VERSION_MARKER) {
+      return Keystore.deserializeUnversioned(buf);
+    }
+    if (buf[1] !== Keystore.VERSION) {
+      throw new Error(`Unsupported keystore version: ${buf[1]}`);
+    }
+    const reader = new Reader(buf.subarray(2));
+    const entryCount = reader.u32();
+    const entries = [];
+    for (let i = 0; i < entryCount; i++) {
+      entries.push(KeystoreEntry.deserialize(reader));
+    }
+    reader.finish();
+    return new Keystore(entries);
+  }
+
+  /**
+   * Deserialize a keystore written before the layout was versioned: a `u32`
+   * key count, then the keys
+   * @param buf A buffer
+   * @returns The deserialized keystore
+   */
+  private static deserializeUnversioned(buf: Buffer): Keystore {
     const entryCount = buf.subarray(0, 4).readUInt32LE(0);
     let data = buf.subarray(4);
     const entries = [];
     for (let i = 0; i < entryCount; i++) {
       const type = data[0];
       const packedLength = getEntryPackedLength(type);
-      const entry = deserializeEntry(type, data.subarray(1, packedLength));
+      const key = deserializeEntry(type, data.subarray(1, packedLength));
       data = data.subarray(packedLength);
-      entries.push(entry);
+      entries.push(new KeystoreEntry(key));
     }
     return new Keystore(entries);
   }
 }
 
+/**
+ * A key in a keystore, with its configuration
+ */
+export class KeystoreEntry {
+  key: EncryptionKeyConfig;
+  config: EntryConfig;
+  constructor(key: EncryptionKeyConfig, config = new EntryConfig()) {
+    this.key = key;
+    this.config = config;
+  }
+
+  /**
+   * Get the packed length of the entry, length prefixes included
+   * @returns The packed length of the entry
+   */
+  getPackedLength(): number {
+    return 4 + this.key.getPackedLength() + 4 + this.config.serialize().length;
+  }
+
+  /**
+   * Serialize the entry: the key, then the configuration, each prefixed
+   * with its `u32` length
+   * @returns A buffer containing the serialized entry
+   */
+  serialize(): Buffer {
+    return Buffer.concat([
+      serializeBytes(this.key.serialize()),
+      serializeBytes(this.config.serialize()),
+    ]);
+  }
+
+  /**
+   * Deserialize an entry
+   * @param reader A reader positioned at the entry
+   * @returns The deserialized entry
+   */
+  static deserialize(reader: Reader): KeystoreEntry {
+    const keyBuf = reader.bytes(reader.u32());
+    const type = keyBuf[0];
+    if (keyBuf.length !== getEntryPackedLength(type)) {
+      throw new Error(`Invalid key length for type ${type}: ${keyBuf.length}`);
+    }
+    const key = deserializeEntry(type, keyBuf.subarray(1));
+    const configReader = new Reader(reader.bytes(reader.u32()));
+    const config = EntryConfig.deserialize(configReader);
+    configReader.finish();
+    return new KeystoreEntry(key, config);
+  }
+}
+
+/**
+ * Lifecycle status of a keystore entry
+ */
+export enum KeyStatus {
+  // The key may be used by senders
+  Active = 0,
+  // The key was withdrawn and must not be used by senders
+  Revoked = 1,
+  // The key was rotated out: senders must not use it, but the owner keeps it
+  // to decrypt earlier messages
+  Retired = 2,
+}
+
+/**
+ * Descriptive metadata for a keystore entry
+ */
+export interface EntryMetadata {
+  // Human-readable label, e.g. "Laptop key"
+  label: string | null;
+  // Name of the device holding the secret key
+  device: string | null;
+  // Slot at which the entry was added
+  createdAtSlot: bigint | null;
+  // Origin of the application that added the entry
+  origin: string | null;
+}
+
+/**
+ * Per-entry configuration, serialized with borsh like the Rust client's
+ * `EntryConfig`
+ */
+export class EntryConfig {
+  // Lifecycle status of the key
+  status: KeyStatus = KeyStatus.Active;
+  // Unix timestamp from which senders must stop using the key
+  expiresAt: bigint | null = null;
+  // Whether this is the owner's preferred key for its algorithm
+  primary = false;
+  // Bit set of allowed uses; empty places no restriction on the key
+  usage = 0;
+  // Descriptive metadata for displaying the entry
+  metadata: EntryMetadata = {
+    label: null,
+    device: null,
+    createdAtSlot: null,
+    origin: null,
+  };
+
+  /**
+   * Serialize the configuration
+   * @returns A buffer containing the serialized configuration
+   */
+  serialize(): Buffer {
+    return Buffer.concat([
+      Buffer.from([this.status]),
+      serializeOption(this.expiresAt, (value) => {
+        const buf = Buffer.alloc(8);
+        buf.writeBigInt64LE(value);
+        return buf;
+      }),
+      Buffer.from([this.primary ? 1 : 0, this.usage]),
+      serializeOption(this.metadata.label, serializeString),
+      serializeOption(this.metadata.device, serializeString),
+      serializeOption(this.metadata.createdAtSlot, (value) => {
+        const buf = Buffer.alloc(8);
+        buf.writeBigUInt64LE(value);
+        return buf;
+      }),
+      serializeOption(this.metadata.origin, serializeString),
+    ]);
+  }
+
+  /**
+   * Deserialize a configuration
+   * @param reader A reader positioned at the configuration
+   * @returns The deserialized configuration
+   */
+  static deserialize(reader: Reader): EntryConfig {
+    const config = new EntryConfig();
+    const status = reader.u8();
+    if (!(status in KeyStatus)) {
+      throw new Error(`Unknown key status: ${status}`);
+    }
+    config.status = status;
+    config.expiresAt = reader.option(() => reader.i64());
+    config.primary = reader.bool();
+    config.usage = reader.u8();
+    config.metadata = {
+      label: reader.option(() => reader.string()),
+      device: reader.option(() => reader.string()),
+      createdAtSlot: reader.option(() => reader.u64()),
+      origin: reader.option(() => reader.string()),
+    };
+    return config;
+  }
+}
+
+/**
+ * Reads borsh-encoded values from a buffer
+ */
+class Reader {
+  private buf: Buffer;
+  private offset = 0;
+  constructor(buf: Buffer) {
+    this.buf = buf;
+  }
+
+  bytes(length: number): Buffer {
+    if (this.offset + length > this.buf.length) {
+      throw new Error("Unexpected end of keystore data");
+    }
+    const bytes = this.buf.subarray(this.offset, this.offset + length);
+    this.offset += length;
+    return bytes;
+  }
+  u8(): number {
+    return this.bytes(1)[0];
+  }
+  bool(): boolean {
+    const value = this.u8();
+    if (value > 1) {
+      throw new Error(`Invalid bool: ${value}`);
+    }
+    return value === 1;
+  }
+  u32(): number {
+    return this.bytes(4).readUInt32LE(0);
+  }
+  i64(): bigint {
+    return this.bytes(8).readBigInt64LE(0);
+  }
+  u64(): bigint {
+    return this.bytes(8).readBigUInt64LE(0);
+  }
+  string(): string {
+    return this.bytes(this.u32()).toString("utf8");
+  }
+  option<T>(read: () => T): T | null {
+    return this.bool() ? read() : null;
+  }
+  // Fail if any bytes are left unread
+  finish() {
+    if (this.offset !== this.buf.length) {
+      throw new Error("Unexpected trailing keystore data");
+    }
+  }
+}
+
+function serializeBytes(bytes: Buffer): Buffer {
+  const length = Buffer.alloc(4);
+  length.writeUInt32LE(bytes.length);
+  return Buffer.concat([length, bytes]);
+}
+
+function serializeString(value: string): Buffer {
+  return serializeBytes(Buffer.from(value, "utf8"));
+}
+
+function serializeOption<T>(
+  value: T | null,
+  serialize: (value: T) => Buffer,
+): Buffer {
+  if (value === null) {
+    return Buffer.from([0]);
+  }
+  return Buffer.concat([Buffer.from([1]), serialize(value)]);
+}
+
 /**
  * An enum for defining recognized encryption algorithms
  */
@@ -11,8 +11,11 @@ import {
 import {
   Curve25519,
   EncryptionKeyConfig,
+  EntryConfig,
+  KeyStatus,
   Keyring,
   Keystore,
+  KeystoreEntry,
   Rsa,
 } from "../src";
 
@@ -43,12 +46,16 @@ describe("Keyring Program Tests", async () => {
 
   async function getFundRentInstruction(
     authority: PublicKey,
-    newSpace: number,
+    keystore: Keystore,
   ): Promise<TransactionInstruction> {
+    const minimumBalance = await connection.getMinimumBalanceForRentExemption(
+      keystore.serialize().length,
+    );
+    const keyringAccount = await keyring.getKeyringAccount(authority);
     return SystemProgram.transfer({
       fromPubkey: authority,
       toPubkey: keyring.getKeyringAddress(authority)[0],
-      lamports: await connection.getMinimumBalanceForRentExemption(newSpace),
+      lamports: Math.max(minimumBalance - keyringAccount.lamports, 0),
     });
   }
 
@@ -102,10 +109,11 @@ describe("Keyring Program Tests", async () => {
    */
   it("Can add a key", async () => {
     const newKey = testCurve25519Key;
+    const mockKeystore = new Keystore([new KeystoreEntry(testCurve25519Key)]);
 
     const fundRentInstruction = await getFundRentInstruction(
       authority.publicKey,
-      newKey.getPackedLength(),
+      mockKeystore,
     );
 
     const instruction = await keyring.addEntryInstruction(
@@ -129,7 +137,6 @@ describe("Keyring Program Tests", async () => {
 
     // Check to make sure the key was added
     const keystore = await keyring.getKeystore(authority.publicKey);
-    const mockKeystore = new Keystore([testCurve25519Key]);
     assert(
       JSON.stringify(keystore) == JSON.stringify(mockKeystore),
       "Keystores do not match!",
@@ -141,10 +148,14 @@ describe("Keyring Program Tests", async () => {
    */
   it("Can add another key", async () => {
     const newKey = testRsaKey;
+    const mockKeystore = new Keystore([
+      new KeystoreEntry(testCurve25519Key),
+      new KeystoreEntry(testRsaKey),
+    ]);
 
     const fundRentInstruction = await getFundRentInstruction(
       authority.publicKey,
-      newKey.getPackedLength(),
+      mockKeystore,
     );
 
     const instruction = await keyring.addEntryInstruction(
@@ -168,7 +179,6 @@ describe("Keyring Program Tests", async () => {
 
     // Check to make sure the key was added
     const keystore = await keyring.getKeystore(authority.publicKey);
-    const mockKeystore = new Keystore([testCurve25519Key, testRsaKey]);
     assert(
       JSON.stringify(keystore) == JSON.stringify(mockKeystore),
       "Keystores do not match!",
@@ -200,10 +210,66 @@ describe("Keyring Program Tests", async () => {
 
     // Check to make sure the key was added
     const keystore = await keyring.getKeystore(authority.publicKey);
-    const mockKeystore = new Keystore([testRsaKey]);
+    const mockKeystore = new Keystore([new KeystoreEntry(testRsaKey)]);
     assert(
       JSON.stringify(keystore) == JSON.stringify(mockKeystore),
       "Keystores do not match!",
     );
   });
 });
+
+/**
+ * Keystore layout tests, run without a validator
+ */
+describe("Keystore Serialization Tests", () => {
+  it("Can round-trip the versioned layout", () => {
+    const config = new EntryConfig();
+    config.status = KeyStatus.Retired;
+    config.primary = true;
+    config.metadata.label = "Laptop key";
+    const keystore = new Keystore([
+      new KeystoreEntry(getTestCurve25519Key(), config),
+      new KeystoreEntry(getTestRsaKey()),
+    ]);
+
+    const buf = keystore.serialize();
+    assert.equal(buf.length, keystore.packedLength());
+    assert.equal(buf[0], Keystore.VERSION_MARKER);
+    assert.equal(buf[1], Keystore.VERSION);
+    assert.equal(buf.readUInt32LE(2), 2);
+    // Each key and configuration is prefixed with its length
+    assert.equal(buf.readUInt32LE(6), Curve25519.PACKED_LENGTH);
+    assert.deepStrictEqual(Keystore.deserialize(buf), keystore);
+  });
+
+  it("Serializes the default configuration like borsh", () => {
+    assert.deepStrictEqual(new EntryConfig().serialize(), Buffer.alloc(8));
+  });
+
+  it("Can read an unversioned keystore", () => {
+    const curve25519Key = getTestCurve25519Key();
+    const rsaKey = getTestRsaKey();
+    const count = Buffer.alloc(4);
+    count.writeUInt32LE(2);
+    const buf = Buffer.concat([
+      count,
+      curve25519Key.serialize(),
+      rsaKey.serialize(),
+    ]);
+
+    // Every key gets the default configuration
+    assert.deepStrictEqual(
+      Keystore.deserialize(buf),
+      new Keystore([
+        new KeystoreEntry(curve25519Key),
+        new KeystoreEntry(rsaKey),
+      ]),
+    );
+  });
+
+  it("Fails to read an unknown version", () => {
+    const buf = new Keystore([]).serialize();
+    buf[1] = Keystore.VERSION + 1;
+    assert.throws(() => Keystore.deserialize(buf));
+  });
+});
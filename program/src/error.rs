@@ -0,0 +1,11 @@
+//! Error types
+
+use spl_program_error::*;
+
+/// Errors that may be returned by the Keyring program
+#[spl_program_error]
+pub enum KeyringProgramError {
+    /// The keystore holds the same key in more than one entry
+    #[error("Keystore holds a duplicate key")]
+    DuplicateEntry,
+}
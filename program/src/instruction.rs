@@ -1,7 +1,7 @@
 //! Keyring Program instructions
 
 use {
-    crate::state::Keyring,
+    crate::state::{Keyring, KeyringStaging},
     solana_program::{
         instruction::{AccountMeta, Instruction},
         program_error::ProgramError,
@@ -35,6 +35,77 @@ pub enum KeyringProgramInstruction {
         /// Vector of bytes to be passed in as a new TLV-based keystore entry
         data: Vec<u8>,
     },
+    /// Write a chunk of a serialized keystore into the keyring
+    ///
+    /// Used when a keystore is too large to be sent in a single
+    /// `UpdateKeyring` transaction. The keyring account is resized to `len`
+    /// bytes, then `data` is written starting at `offset`. Writing every
+    /// chunk of the keystore with the same `len` leaves the account holding
+    /// the complete keystore.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[w]` Keyring
+    ///   1. `[s]` Authority
+    WriteKeyring {
+        /// Total length of the keystore being written
+        len: u32,
+        /// Offset into the keystore at which to write `data`
+        offset: u32,
+        /// Chunk of the serialized keystore
+        data: Vec<u8>,
+    },
+    /// Close the keyring account
+    ///
+    /// The keyring's lamports are moved to the destination and the emptied
+    /// account is returned to the system program, so the keyring can be
+    /// created again later.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[w]` Keyring
+    ///   1. `[s]` Authority
+    ///   2. `[w]` Destination for the keyring's lamports
+    CloseKeyring,
+    /// Write a chunk of a serialized keystore into the authority's staging
+    /// account
+    ///
+    /// Used to replace a keystore too large for a single `UpdateKeyring`
+    /// transaction atomically: every chunk is staged first, and
+    /// `CommitStaging` then swaps the whole keystore in at once. The staging
+    /// account is created on the first write, sized to `len` bytes and made
+    /// rent-exempt by the payer; a later write with a different `len`
+    /// resizes it, the payer covering any rent it lacks. `data` is written
+    /// starting at `offset`.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[w]` Staging
+    ///   1. `[s]` Authority
+    ///   2. `[w, s]` Payer
+    ///   3. `[]` System program
+    WriteStaging {
+        /// Total length of the keystore being staged
+        len: u32,
+        /// Offset into the keystore at which to write `data`
+        offset: u32,
+        /// Chunk of the serialized keystore
+        data: Vec<u8>,
+    },
+    /// Overwrite the keyring with the staged keystore and close the staging
+    /// account
+    ///
+    /// The staged data is checked for duplicate keys like `UpdateKeyring`
+    /// data. The staging account's lamports cover any rent the resized
+    /// keyring lacks, and the rest go to the destination.
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[w]` Keyring
+    ///   1. `[s]` Authority
+    ///   2. `[w]` Staging
+    ///   3. `[w]` Destination for the staging account's remaining lamports
+    CommitStaging,
 }
 
 impl KeyringProgramInstruction {
@@ -49,6 +120,24 @@ impl KeyringProgramInstruction {
                 buf.push(1);
                 buf.extend_from_slice(data);
             }
+            KeyringProgramInstruction::WriteKeyring { len, offset, data } => {
+                buf.push(2);
+                buf.extend_from_slice(&len.to_le_bytes());
+                buf.extend_from_slice(&offset.to_le_bytes());
+                buf.extend_from_slice(data);
+            }
+            KeyringProgramInstruction::CloseKeyring => {
+                buf.push(3);
+            }
+            KeyringProgramInstruction::WriteStaging { len, offset, data } => {
+                buf.push(4);
+                buf.extend_from_slice(&len.to_le_bytes());
+                buf.extend_from_slice(&offset.to_le_bytes());
+                buf.extend_from_slice(data);
+            }
+            KeyringProgramInstruction::CommitStaging => {
+                buf.push(5);
+            }
         }
         buf
     }
@@ -63,9 +152,38 @@ impl KeyringProgramInstruction {
             1 => KeyringProgramInstruction::UpdateKeyring {
                 data: rest.to_vec(),
             },
+            2 => {
+                let (len, rest) = Self::unpack_u32(rest)?;
+                let (offset, rest) = Self::unpack_u32(rest)?;
+                KeyringProgramInstruction::WriteKeyring {
+                    len,
+                    offset,
+                    data: rest.to_vec(),
+                }
+            }
+            3 => KeyringProgramInstruction::CloseKeyring,
+            4 => {
+                let (len, rest) = Self::unpack_u32(rest)?;
+                let (offset, rest) = Self::unpack_u32(rest)?;
+                KeyringProgramInstruction::WriteStaging {
+                    len,
+                    offset,
+                    data: rest.to_vec(),
+                }
+            }
+            5 => KeyringProgramInstruction::CommitStaging,
             _ => return Err(ProgramError::InvalidInstructionData),
         })
     }
+
+    fn unpack_u32(input: &[u8]) -> Result<(u32, &[u8]), ProgramError> {
+        let value = input
+            .get(..4)
+            .and_then(|bytes| bytes.try_into().ok())
+            .map(u32::from_le_bytes)
+            .ok_or(ProgramError::InvalidInstructionData)?;
+        Ok((value, &input[4..]))
+    }
 }
 
 /// Creates a 'CreateKeyring' instruction.
@@ -112,6 +230,105 @@ pub fn update_keyring(
     })
 }
 
+/// Creates a 'WriteKeyring' instruction.
+pub fn write_keyring(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    len: u32,
+    offset: u32,
+    data: Vec<u8>,
+) -> Result<Instruction, ProgramError> {
+    let keyring = Keyring::pda(program_id, authority).0;
+
+    let data = KeyringProgramInstruction::WriteKeyring { len, offset, data }.pack();
+
+    let accounts = vec![
+        AccountMeta::new(keyring, false),
+        AccountMeta::new(*authority, true),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'CloseKeyring' instruction.
+pub fn close_keyring(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    destination: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let keyring = Keyring::pda(program_id, authority).0;
+
+    let data = KeyringProgramInstruction::CloseKeyring.pack();
+
+    let accounts = vec![
+        AccountMeta::new(keyring, false),
+        AccountMeta::new_readonly(*authority, true),
+        AccountMeta::new(*destination, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'WriteStaging' instruction.
+pub fn write_staging(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    payer: &Pubkey,
+    len: u32,
+    offset: u32,
+    data: Vec<u8>,
+) -> Result<Instruction, ProgramError> {
+    let staging = KeyringStaging::pda(program_id, authority).0;
+
+    let data = KeyringProgramInstruction::WriteStaging { len, offset, data }.pack();
+
+    let accounts = vec![
+        AccountMeta::new(staging, false),
+        AccountMeta::new_readonly(*authority, true),
+        AccountMeta::new(*payer, true),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
+/// Creates a 'CommitStaging' instruction.
+pub fn commit_staging(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    destination: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let keyring = Keyring::pda(program_id, authority).0;
+    let staging = KeyringStaging::pda(program_id, authority).0;
+
+    let data = KeyringProgramInstruction::CommitStaging.pack();
+
+    let accounts = vec![
+        AccountMeta::new(keyring, false),
+        AccountMeta::new_readonly(*authority, true),
+        AccountMeta::new(staging, false),
+        AccountMeta::new(*destination, false),
+    ];
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts,
+        data,
+    })
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -140,4 +357,102 @@ mod test {
             KeyringProgramInstruction::UpdateKeyring { data }.pack()
         );
     }
+
+    #[test]
+    fn write_keyring_instruction() {
+        let program_id = Pubkey::new_unique();
+        let authority = Pubkey::new_unique();
+        let data = vec![1, 2, 3];
+
+        let instruction = write_keyring(&program_id, &authority, 10, 4, data.clone()).unwrap();
+        assert_eq!(
+            instruction.data,
+            KeyringProgramInstruction::WriteKeyring {
+                len: 10,
+                offset: 4,
+                data: data.clone(),
+            }
+            .pack()
+        );
+        assert_eq!(
+            KeyringProgramInstruction::unpack(&instruction.data).unwrap(),
+            KeyringProgramInstruction::WriteKeyring {
+                len: 10,
+                offset: 4,
+                data,
+            }
+        );
+    }
+
+    #[test]
+    fn write_keyring_instruction_too_short() {
+        assert_eq!(
+            KeyringProgramInstruction::unpack(&[2, 10, 0, 0, 0, 4]).unwrap_err(),
+            ProgramError::InvalidInstructionData
+        );
+    }
+
+    #[test]
+    fn close_keyring_instruction() {
+        let program_id = Pubkey::new_unique();
+        let authority = Pubkey::new_unique();
+        let destination = Pubkey::new_unique();
+
+        let instruction = close_keyring(&program_id, &authority, &destination).unwrap();
+        assert_eq!(instruction.accounts[2].pubkey, destination);
+        assert_eq!(
+            KeyringProgramInstruction::unpack(&instruction.data).unwrap(),
+            KeyringProgramInstruction::CloseKeyring
+        );
+    }
+
+    #[test]
+    fn write_staging_instruction() {
+        let program_id = Pubkey::new_unique();
+        let authority = Pubkey::new_unique();
+        let payer = Pubkey::new_unique();
+        let data = vec![1, 2, 3];
+
+        let instruction =
+            write_staging(&program_id, &authority, &payer, 10, 4, data.clone()).unwrap();
+        assert_eq!(
+            instruction.accounts[0].pubkey,
+            KeyringStaging::pda(&program_id, &authority).0
+        );
+        assert_eq!(instruction.accounts[2].pubkey, payer);
+        assert_eq!(
+            KeyringProgramInstruction::unpack(&instruction.data).unwrap(),
+            KeyringProgramInstruction::WriteStaging {
+                len: 10,
+                offset: 4,
+                data,
+            }
+        );
+        assert_eq!(
+            KeyringProgramInstruction::unpack(&[4, 10, 0, 0, 0, 4]).unwrap_err(),
+            ProgramError::InvalidInstructionData
+        );
+    }
+
+    #[test]
+    fn commit_staging_instruction() {
+        let program_id = Pubkey::new_unique();
+        let authority = Pubkey::new_unique();
+        let destination = Pubkey::new_unique();
+
+        let instruction = commit_staging(&program_id, &authority, &destination).unwrap();
+        assert_eq!(
+            instruction.accounts[0].pubkey,
+            Keyring::pda(&program_id, &authority).0
+        );
+        assert_eq!(
+            instruction.accounts[2].pubkey,
+            KeyringStaging::pda(&program_id, &authority).0
+        );
+        assert_eq!(instruction.accounts[3].pubkey, destination);
+        assert_eq!(
+            KeyringProgramInstruction::unpack(&instruction.data).unwrap(),
+            KeyringProgramInstruction::CommitStaging
+        );
+    }
 }
@@ -4,6 +4,7 @@
 //! Crate defining the Keyring Program
 
 mod entrypoint;
+pub mod error;
 pub mod instruction;
 pub mod processor;
 pub mod state;
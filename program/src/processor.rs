@@ -1,16 +1,19 @@
 //! Program processor
 
 use {
-    crate::{instruction::KeyringProgramInstruction, state::Keyring},
+    crate::{
+        instruction::KeyringProgramInstruction,
+        state::{Keyring, KeyringStaging},
+    },
     solana_program::{
         account_info::{next_account_info, AccountInfo},
         entrypoint::ProgramResult,
         msg,
-        program::invoke_signed,
+        program::{invoke, invoke_signed},
         program_error::ProgramError,
         pubkey::Pubkey,
         rent::Rent,
-        system_instruction,
+        system_instruction, system_program,
     },
 };
 
@@ -55,7 +58,8 @@ pub fn process_create_keyring(program_id: &Pubkey, accounts: &[AccountInfo]) ->
 
 /// Processes a `UpdateKeyring` instruction.
 ///
-/// Simply overwrites the entire account buffer with the new data.
+/// Overwrites the entire account buffer with the new data, after checking it
+/// for duplicate keys.
 pub fn process_update_keyring(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
@@ -71,6 +75,8 @@ pub fn process_update_keyring(
         check_authority(authority_info)?;
     }
 
+    Keyring::check_unique_keys(&data)?;
+
     let new_len = data.len();
     keyring_info.realloc(new_len, true)?;
     keyring_info.try_borrow_mut_data()?[..].copy_from_slice(&data);
@@ -78,6 +84,194 @@ pub fn process_update_keyring(
     Ok(())
 }
 
+/// Processes a `WriteKeyring` instruction.
+///
+/// Resizes the account buffer to `len` and writes the chunk at `offset`.
+/// Once the final chunk is written, the whole buffer is checked for
+/// duplicate keys.
+pub fn process_write_keyring(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    len: u32,
+    offset: u32,
+    data: Vec<u8>,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let keyring_info = next_account_info(account_info_iter)?;
+    let authority_info = next_account_info(account_info_iter)?;
+
+    {
+        Keyring::check_pda(program_id, authority_info.key, keyring_info.key)?;
+        check_authority(authority_info)?;
+    }
+
+    let new_len = len as usize;
+    let start = offset as usize;
+    let end = start
+        .checked_add(data.len())
+        .filter(|end| *end <= new_len)
+        .ok_or(ProgramError::InvalidArgument)?;
+
+    keyring_info.realloc(new_len, true)?;
+    keyring_info.try_borrow_mut_data()?[start..end].copy_from_slice(&data);
+
+    if end == new_len {
+        Keyring::check_unique_keys(&keyring_info.try_borrow_data()?)?;
+    }
+
+    Ok(())
+}
+
+/// Processes a `CloseKeyring` instruction.
+///
+/// Moves the keyring's lamports to the destination and hands the emptied
+/// account back to the system program.
+pub fn process_close_keyring(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let keyring_info = next_account_info(account_info_iter)?;
+    let authority_info = next_account_info(account_info_iter)?;
+    let destination_info = next_account_info(account_info_iter)?;
+
+    {
+        Keyring::check_pda(program_id, authority_info.key, keyring_info.key)?;
+        check_authority(authority_info)?;
+    }
+    if destination_info.key == keyring_info.key {
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    let destination_lamports = destination_info
+        .lamports()
+        .checked_add(keyring_info.lamports())
+        .ok_or(ProgramError::InvalidArgument)?;
+    **destination_info.try_borrow_mut_lamports()? = destination_lamports;
+    **keyring_info.try_borrow_mut_lamports()? = 0;
+
+    keyring_info.realloc(0, true)?;
+    keyring_info.assign(&system_program::id());
+
+    Ok(())
+}
+
+/// Processes a `WriteStaging` instruction.
+///
+/// Creates the staging account on the first write, or resizes it to `len`,
+/// with the payer funding its rent, then writes the chunk at `offset`.
+pub fn process_write_staging(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    len: u32,
+    offset: u32,
+    data: Vec<u8>,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let staging_info = next_account_info(account_info_iter)?;
+    let authority_info = next_account_info(account_info_iter)?;
+    let payer_info = next_account_info(account_info_iter)?;
+    let _system_program_info = next_account_info(account_info_iter)?;
+
+    let bump_seed = {
+        check_authority(authority_info)?;
+        KeyringStaging::check_pda(program_id, authority_info.key, staging_info.key)?
+    };
+
+    let new_len = len as usize;
+    let start = offset as usize;
+    let end = start
+        .checked_add(data.len())
+        .filter(|end| *end <= new_len)
+        .ok_or(ProgramError::InvalidArgument)?;
+
+    let minimum_balance = Rent::default().minimum_balance(new_len);
+    if staging_info.owner != program_id {
+        let mut signer_seeds = KeyringStaging::seeds(authority_info.key);
+        let bump_signer_seed = [bump_seed];
+        signer_seeds.push(&bump_signer_seed);
+
+        invoke_signed(
+            &system_instruction::create_account(
+                payer_info.key,
+                staging_info.key,
+                minimum_balance,
+                new_len as u64,
+                program_id,
+            ),
+            &[payer_info.clone(), staging_info.clone()],
+            &[&signer_seeds],
+        )?;
+    } else {
+        let shortfall = minimum_balance.saturating_sub(staging_info.lamports());
+        if shortfall > 0 {
+            invoke(
+                &system_instruction::transfer(payer_info.key, staging_info.key, shortfall),
+                &[payer_info.clone(), staging_info.clone()],
+            )?;
+        }
+        staging_info.realloc(new_len, true)?;
+    }
+
+    staging_info.try_borrow_mut_data()?[start..end].copy_from_slice(&data);
+
+    Ok(())
+}
+
+/// Processes a `CommitStaging` instruction.
+///
+/// Overwrites the keyring with the staged data, after checking it for
+/// duplicate keys, then closes the staging account. Its lamports top up the
+/// keyring's rent, and the rest go to the destination.
+pub fn process_commit_staging(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let keyring_info = next_account_info(account_info_iter)?;
+    let authority_info = next_account_info(account_info_iter)?;
+    let staging_info = next_account_info(account_info_iter)?;
+    let destination_info = next_account_info(account_info_iter)?;
+
+    {
+        Keyring::check_pda(program_id, authority_info.key, keyring_info.key)?;
+        KeyringStaging::check_pda(program_id, authority_info.key, staging_info.key)?;
+        check_authority(authority_info)?;
+    }
+    if staging_info.owner != program_id {
+        return Err(ProgramError::UninitializedAccount);
+    }
+    if destination_info.key == keyring_info.key || destination_info.key == staging_info.key {
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    let data = staging_info.try_borrow_data()?.to_vec();
+    Keyring::check_unique_keys(&data)?;
+
+    keyring_info.realloc(data.len(), true)?;
+    keyring_info.try_borrow_mut_data()?[..].copy_from_slice(&data);
+
+    let staged_lamports = staging_info.lamports();
+    let rent = Rent::default()
+        .minimum_balance(data.len())
+        .saturating_sub(keyring_info.lamports())
+        .min(staged_lamports);
+    let keyring_lamports = keyring_info
+        .lamports()
+        .checked_add(rent)
+        .ok_or(ProgramError::InvalidArgument)?;
+    let destination_lamports = destination_info
+        .lamports()
+        .checked_add(staged_lamports - rent)
+        .ok_or(ProgramError::InvalidArgument)?;
+    **keyring_info.try_borrow_mut_lamports()? = keyring_lamports;
+    **destination_info.try_borrow_mut_lamports()? = destination_lamports;
+    **staging_info.try_borrow_mut_lamports()? = 0;
+
+    staging_info.realloc(0, true)?;
+    staging_info.assign(&system_program::id());
+
+    Ok(())
+}
+
 /// Processes a `KeyringProgramInstruction` instruction.
 pub fn process(program_id: &Pubkey, accounts: &[AccountInfo], input: &[u8]) -> ProgramResult {
     let instruction = KeyringProgramInstruction::unpack(input)?;
@@ -91,5 +285,21 @@ pub fn process(program_id: &Pubkey, accounts: &[AccountInfo], input: &[u8]) -> P
             msg!("Instruction: UpdateKeyring");
             process_update_keyring(program_id, accounts, data)
         }
+        KeyringProgramInstruction::WriteKeyring { len, offset, data } => {
+            msg!("Instruction: WriteKeyring");
+            process_write_keyring(program_id, accounts, len, offset, data)
+        }
+        KeyringProgramInstruction::CloseKeyring => {
+            msg!("Instruction: CloseKeyring");
+            process_close_keyring(program_id, accounts)
+        }
+        KeyringProgramInstruction::WriteStaging { len, offset, data } => {
+            msg!("Instruction: WriteStaging");
+            process_write_staging(program_id, accounts, len, offset, data)
+        }
+        KeyringProgramInstruction::CommitStaging => {
+            msg!("Instruction: CommitStaging");
+            process_commit_staging(program_id, accounts)
+        }
     }
 }
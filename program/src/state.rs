@@ -1,6 +1,9 @@
 //! Keyring Program state
 
-use solana_program::{program_error::ProgramError, pubkey::Pubkey};
+use {
+    crate::error::KeyringProgramError,
+    solana_program::{entrypoint::ProgramResult, program_error::ProgramError, pubkey::Pubkey},
+};
 
 /// Struct for managing keystore state
 pub struct Keyring;
@@ -8,6 +11,81 @@ impl Keyring {
     /// String literal seed prefix
     const SEED_PREFIX: &'static str = "keyring";
 
+    /// First byte of a versioned keystore
+    pub const VERSION_MARKER: u8 = 0xff;
+    /// Keystore layout version the program checks
+    pub const VERSION: u8 = 1;
+
+    /// Returns the seeds for this account as a vector of slices
+    pub fn seeds(authority: &Pubkey) -> Vec<&[u8]> {
+        vec![Self::SEED_PREFIX.as_bytes(), authority.as_ref()]
+    }
+
+    /// Returns the program-derived address and bump seed for this account type
+    /// using the provided arguments
+    pub fn pda(program_id: &Pubkey, authority: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&Self::seeds(authority), program_id)
+    }
+
+    /// Validates a passed `Pubkey` against the `Pubkey` returned from the
+    /// `pda(&self, ..)` method, then returns the bump seed
+    pub fn check_pda(
+        program_id: &Pubkey,
+        authority: &Pubkey,
+        pda: &Pubkey,
+    ) -> Result<u8, ProgramError> {
+        let (pda_check, bump_seed) = Self::pda(program_id, authority);
+        if pda != &pda_check {
+            return Err(ProgramError::InvalidSeeds);
+        }
+        Ok(bump_seed)
+    }
+
+    /// Rejects keystore data that holds the same key in more than one entry
+    ///
+    /// The program doesn't interpret keys, but it relies on the client's
+    /// framing: `VERSION_MARKER` and `VERSION`, a `u32` entry count, then the
+    /// entries, each a length-prefixed key followed by a length-prefixed
+    /// configuration. Data that doesn't follow this framing, such as a
+    /// keystore written before the layout was versioned, is left to the
+    /// client.
+    pub fn check_unique_keys(data: &[u8]) -> ProgramResult {
+        if let Some(keys) = Self::entry_keys(data) {
+            for (i, key) in keys.iter().enumerate() {
+                if keys[..i].contains(key) {
+                    return Err(KeyringProgramError::DuplicateEntry.into());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the key bytes of each entry, or `None` if the data doesn't
+    /// follow the entry framing
+    fn entry_keys(data: &[u8]) -> Option<Vec<&[u8]>> {
+        let rest = data.strip_prefix(&[Self::VERSION_MARKER, Self::VERSION])?;
+        let (count, mut rest) = split_u32(rest)?;
+        let mut keys = Vec::new();
+        for _ in 0..count {
+            let (key, next) = split_prefixed(rest)?;
+            let (_config, next) = split_prefixed(next)?;
+            keys.push(key);
+            rest = next;
+        }
+        rest.is_empty().then_some(keys)
+    }
+}
+
+/// Struct for managing the staging account of an atomic keystore write
+///
+/// A keystore too large for one transaction is written into the staging
+/// account in chunks, then copied into the keyring by a single
+/// `CommitStaging` instruction.
+pub struct KeyringStaging;
+impl KeyringStaging {
+    /// String literal seed prefix
+    const SEED_PREFIX: &'static str = "keyring_staging";
+
     /// Returns the seeds for this account as a vector of slices
     pub fn seeds(authority: &Pubkey) -> Vec<&[u8]> {
         vec![Self::SEED_PREFIX.as_bytes(), authority.as_ref()]
@@ -34,6 +112,17 @@ impl Keyring {
     }
 }
 
+fn split_u32(data: &[u8]) -> Option<(u32, &[u8])> {
+    let value = data.get(..4)?.try_into().ok().map(u32::from_le_bytes)?;
+    Some((value, &data[4..]))
+}
+
+fn split_prefixed(data: &[u8]) -> Option<(&[u8], &[u8])> {
+    let (len, rest) = split_u32(data)?;
+    let len = len as usize;
+    Some((rest.get(..len)?, &rest[len..]))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -53,4 +142,61 @@ mod tests {
         assert_eq!(pda, check_pda.0);
         assert_eq!(bump_seed, check_pda.1);
     }
+
+    #[test]
+    fn test_staging_seeds() {
+        let program_id = Pubkey::new_unique();
+        let authority = Pubkey::new_unique();
+        let (pda, bump_seed) = KeyringStaging::pda(&program_id, &authority);
+        let check_pda =
+            Pubkey::find_program_address(&[b"keyring_staging", authority.as_ref()], &program_id);
+        assert_eq!(pda, check_pda.0);
+        assert_eq!(bump_seed, check_pda.1);
+        assert_ne!(pda, Keyring::pda(&program_id, &authority).0);
+        assert_eq!(
+            KeyringStaging::check_pda(&program_id, &authority, &pda),
+            Ok(bump_seed)
+        );
+        assert_eq!(
+            KeyringStaging::check_pda(
+                &program_id,
+                &authority,
+                &Keyring::pda(&program_id, &authority).0
+            ),
+            Err(ProgramError::InvalidSeeds)
+        );
+    }
+
+    fn keystore_data(entries: &[(&[u8], &[u8])]) -> Vec<u8> {
+        let mut data = vec![Keyring::VERSION_MARKER, Keyring::VERSION];
+        data.extend_from_slice(&(entries.len() as u32).to_le_bytes());
+        for (key, config) in entries {
+            data.extend_from_slice(&(key.len() as u32).to_le_bytes());
+            data.extend_from_slice(key);
+            data.extend_from_slice(&(config.len() as u32).to_le_bytes());
+            data.extend_from_slice(config);
+        }
+        data
+    }
+
+    #[test]
+    fn test_check_unique_keys() {
+        let unique = keystore_data(&[(&[0, 1, 2], &[0]), (&[1, 1, 2], &[0])]);
+        assert_eq!(Keyring::check_unique_keys(&unique), Ok(()));
+
+        // Same key, different configuration
+        let duplicate = keystore_data(&[(&[0, 1, 2], &[0]), (&[0, 1, 2], &[1])]);
+        assert_eq!(
+            Keyring::check_unique_keys(&duplicate),
+            Err(KeyringProgramError::DuplicateEntry.into())
+        );
+
+        // Data the program can't frame is left alone
+        assert_eq!(Keyring::check_unique_keys(&[]), Ok(()));
+        assert_eq!(Keyring::check_unique_keys(&duplicate[..10]), Ok(()));
+        assert_eq!(Keyring::check_unique_keys(&duplicate[2..]), Ok(()));
+        let mut unknown_version = duplicate;
+        unknown_version[1] = Keyring::VERSION + 1;
+        assert_eq!(Keyring::check_unique_keys(&unknown_version), Ok(()));
+    }
 }